@@ -1,5 +1,5 @@
 use bevy::prelude::World;
-use common::{physics::{Map, Tile, Position, MapCache, Occupier}, behaviors::pathfinder::data::PathfinderGlobalData};
+use common::{physics::{Map, Tile, Position, MapCache, Occupier, Zone}, behaviors::pathfinder::data::PathfinderGlobalData, noise::Noise, time::Clock, spatial::{SpatialIndex, Layer}, character::Sprite};
 use tui::{widgets::{Widget, Paragraph, Block, Borders}, style::{Style, Color}, text::{Span, Spans}};
 
 use crate::constants::sprite_to_str;
@@ -64,8 +64,10 @@ impl<'a> Widget for MapCanvas<'a> {
         };
 
         let mut text = Vec::<Spans>::with_capacity(size_y);
-        let in_vision = map.get_in_vision(self.map_cache, self.vision_position);
+        let in_vision = map.get_in_vision(self.map_cache, self.vision_position, self.world.resource::<Clock>().vision_distance());
         let pathfinder_data = self.world.resource::<PathfinderGlobalData>();
+        let noise = self.world.resource::<Noise>();
+        let spatial_index = self.world.resource::<SpatialIndex>();
         for y in start_y..size_y {
             let mut t = Vec::<Span>::with_capacity(size_x);
             for x in start_x..size_x {
@@ -78,6 +80,16 @@ impl<'a> Widget for MapCanvas<'a> {
                     } else {
                         let character = if DEBUG || in_vision.contains(&Position::new(x as i32, y as i32)) {
                             match tile {
+                                Tile::Ground { occupier: None, .. } if noise.volume_at(&Position::new(x as i32, y as i32)) != 0 => {
+                                    Span::styled("~", Style::default().fg(Color::Magenta))
+                                },
+                                Tile::Ground { occupier: None, .. } if spatial_index.at_layer(&Position::new(x as i32, y as i32), Layer::Floor).next().is_some() => {
+                                    let (txt, color) = sprite_to_str(&Sprite::Item);
+                                    Span::styled(txt, Style::default().fg(color.unwrap_or(Color::White)))
+                                },
+                                Tile::Ground { occupier, zone } if *map.zone(*zone) == Zone::Rubble => {
+                                    get_sprite_from_occupier(occupier, ".")
+                                },
                                 Tile::Ground { occupier, .. } => {
                                     get_sprite_from_occupier(occupier, " ")
                                 },
@@ -9,6 +9,9 @@ pub const fn sprite_to_str(sprite: &Sprite) -> (&'static str, Option<Color>) {
         Sprite::Werewolf => ("W", Some(Color::LightRed)),
         Sprite::Projectile => ("o", Some(Color::White)),
         Sprite::Chest => ("M", Some(Color::Yellow)),
+        Sprite::Door => ("+", Some(Color::Yellow)),
+        Sprite::Stairs => (">", Some(Color::White)),
+        Sprite::Item => ("*", Some(Color::LightGreen)),
         Sprite::Unknown => ("?", None),
     }
 }
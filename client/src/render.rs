@@ -1,6 +1,6 @@
 use std::{time::{Duration, Instant}, thread, sync::mpsc::Receiver};
 use bevy::prelude::{App, ResMut, Query, With, CoreStage, State, Entity, World};
-use common::{physics::*, character::{PlayerInput, MovementInput, PlayerTag, ActionHistory, Health}, dialogue::Dialogue, inventory::{Inventory, Equipment}, ActionInput, Scene, PlayerState, loot_menu::{LootMenu, transfer_item}};
+use common::{physics::*, character::{PlayerInput, MovementInput, PlayerTag, ActionHistory, Health}, dialogue::{Dialogue, resolve}, inventory::{Inventory, Equipment}, ActionInput, Scene, PlayerState, Settings, loot_menu::{LootMenu, transfer_item}, trade::{Trader, propose}, relationship::opinion_of_player, time::Clock, journal::{Journal, accuse}, quest::{Quests, QuestState}};
 use crossterm::{
     terminal::enable_raw_mode, event, execute,
 };
@@ -12,7 +12,7 @@ use tui::{
     style::{Style, Modifier},
     text::{Spans, Span, Text}
 };
-use crate::{canvas::MapCanvas, util::render_inventory};
+use crate::{canvas::MapCanvas, util::{render_inventory, render_trade_inventory}};
 
 enum Event<I> {
     Input(I),
@@ -53,6 +53,8 @@ enum Menu {
     #[default]
     World,
     Inventory,
+    Journal,
+    Quests,
     Settings,
 }
 impl From<&Menu> for usize {
@@ -60,7 +62,9 @@ impl From<&Menu> for usize {
         match input {
             Menu::World => 0,
             Menu::Inventory => 1,
-            Menu::Settings => 2,
+            Menu::Journal => 2,
+            Menu::Quests => 3,
+            Menu::Settings => 4,
         }
     }
 }
@@ -117,7 +121,7 @@ impl MenuOption {
     /// Makes sure index is not greater than count!
     fn check(&mut self, count: usize) {
         if  self.index >= count {
-            self.index = count - 1;
+            self.index = count.saturating_sub(1);
         }
     }
     fn check_from_focus(&mut self, world: &mut World) {
@@ -154,12 +158,20 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let menu_titles = vec!["World", "Inventory", "Settings"];
+    let menu_titles = vec!["World", "Inventory", "Journal", "Quests", "Settings"];
     let mut data = Data::default();
 
     let mut map_cache = MapCache::default();
     // Render
     loop {
+        let clock = app.world.resource::<Clock>();
+        let menu_title = format!(
+            "Menu - Day {} {:02}:{:02} {}",
+            clock.day() + 1,
+            clock.hour(),
+            clock.minute(),
+            if clock.is_night() { clock.moon_phase().name() } else { "Daylight" },
+        );
         terminal.draw(|rect| {
             const MARGIN: u16 = 2;
             // Layout
@@ -232,6 +244,47 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
                             active.select(Some(data.active_option.index));
                             rect.render_stateful_widget(options, state_layout[1], &mut active);
                         },
+                        PlayerState::Looting if app.world.resource::<LootMenu>().trade.is_some() => {
+                            let player = app.world.query_filtered::<Entity, With<PlayerTag>>().single(&app.world);
+                            let opinion = app.world.resource::<LootMenu>().inventory.map_or(0, |entity| opinion_of_player(&mut app.world, entity));
+                            let loot_menu = app.world.resource::<LootMenu>();
+                            if let (Some(trader_entity), Some(trade)) = (loot_menu.inventory, &loot_menu.trade) {
+                                if let (Some(inventory), Some(player_inventory)) = (app.world.get::<Inventory>(trader_entity), app.world.get::<Inventory>(player)) {
+                                    let trader = Trader { inventory, equipment: app.world.get::<Equipment>(trader_entity), opinion };
+                                    let (other_focused, ours_focused) = match data.active_option.focus {
+                                        Focus::Other => (" [Focused]", ""),
+                                        Focus::Ours => ("", " [Focused]"),
+                                    };
+                                    let outcome = match &trade.outcome {
+                                        Some(Ok(())) => " - Deal".to_string(),
+                                        Some(Err(error)) => format!(" - {error}"),
+                                        None => String::new(),
+                                    };
+
+                                    // Trader Inventory
+                                    let title = format!("Their Wares (asking {}){other_focused}", trade.asked_worth(&trader));
+                                    let list = render_trade_inventory(inventory, title, &trade.asked, |item| trader.worth(item, true));
+                                    let mut active = ListState::default();
+                                    active.select(if matches!(data.active_option.focus, Focus::Other) {
+                                        Some(data.active_option.index)
+                                    } else {
+                                        None
+                                    });
+                                    rect.render_stateful_widget(list, state_layout[0], &mut active);
+
+                                    // Player Inventory
+                                    let title = format!("Your Offer (worth {}){outcome}{ours_focused}", trade.offer_worth(player_inventory, &trader));
+                                    let list = render_trade_inventory(player_inventory, title, &trade.offered, |item| trader.worth(item, false));
+                                    let mut active = ListState::default();
+                                    active.select(if matches!(data.active_option.focus, Focus::Ours) {
+                                        Some(data.active_option.index)
+                                    } else {
+                                        None
+                                    });
+                                    rect.render_stateful_widget(list, state_layout[1], &mut active);
+                                }
+                            }
+                        },
                         PlayerState::Looting => {
                             let loot_menu = app.world.resource::<LootMenu>();
                             if let Some(loot_entity) = loot_menu.inventory {
@@ -294,8 +347,49 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
                         app.world.resource_mut::<CameraData>().inventory_selection = camera_data;
                     }
                 },
+                Menu::Journal => {
+                    let journal = app.world.resource::<Journal>();
+                    let entries: Vec<ListItem> = journal.entries()
+                        .iter()
+                        .rev()
+                        .map(|entry| ListItem::new(Text::raw(entry.to_string())))
+                        .collect();
+                    let list = List::new(entries)
+                        .block(Block::default().borders(Borders::ALL).title("Journal"));
+                    rect.render_widget(list, main_layout[1]);
+                },
+                Menu::Quests => {
+                    let quests = app.world.resource::<Quests>();
+                    let mut entries = Vec::<ListItem>::new();
+                    for quest in quests.given().iter().rev() {
+                        let status = match &quest.state {
+                            QuestState::Active(_) => "Active",
+                            QuestState::Completed => "Completed",
+                            QuestState::Failed => "Failed",
+                        };
+                        let mut text = format!("{} [{status}]\n  {}", quest.title, quest.description);
+                        for (index, objective) in quest.objectives.iter().enumerate() {
+                            let done = match &quest.state {
+                                QuestState::Active(done) => done[index],
+                                QuestState::Completed => true,
+                                QuestState::Failed => false,
+                            };
+                            text.push_str(&format!("\n  [{}] {}", if done { "x" } else { " " }, objective.to_string()));
+                        }
+                        entries.push(ListItem::new(Text::raw(text)));
+                    }
+                    let list = List::new(entries)
+                        .block(Block::default().borders(Borders::ALL).title("Quests"));
+                    rect.render_widget(list, main_layout[1]);
+                },
                 Menu::Settings => {
-                    let p = Paragraph::new("<ESC> to quit")
+                    let settings = app.world.resource::<Settings>();
+                    let on_off = |value: bool| if value { "On" } else { "Off" };
+                    let p = Paragraph::new(format!(
+                        "<D> Diagonal movement: {}\n<V> Vi-keys: {} (Shift+J for the journal)\n<ESC> to quit",
+                        on_off(settings.diagonal_movement),
+                        on_off(settings.vi_keys),
+                    ))
                         .block(Block::default().borders(Borders::ALL).title("Settings"));
                     rect.render_widget(p, main_layout[1]);
                 },
@@ -319,7 +413,7 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
                 .collect();
             let tabs = Tabs::new(menu)
                 .select(usize::from(&data.active_menu))
-                .block(Block::default().title("Menu").borders(Borders::ALL))
+                .block(Block::default().title(menu_title.as_str()).borders(Borders::ALL))
                 .style(Style::default().fg(tui::style::Color::White))
                 .highlight_style(Style::default().fg(tui::style::Color::Yellow))
                 .divider("|");
@@ -352,6 +446,7 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
         match rx.recv()? {
             Event::Input(input) => {
                 if let event::Event::Key(key) = input {
+                    let vi_keys = app.world.resource::<Settings>().vi_keys;
                     let mut switch_menu = |menu: &mut Menu| {
                         let mut set_menu = |m: Menu, s: Scene| {
                             if *menu == m { return; }
@@ -364,6 +459,8 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
                         match key.code {
                             event::KeyCode::Char('w') | event::KeyCode::Char('W') => set_menu(Menu::World, Scene::Map),
                             event::KeyCode::Char('i') | event::KeyCode::Char('I') => set_menu(Menu::Inventory, Scene::Inventory),
+                            event::KeyCode::Char('j') | event::KeyCode::Char('J') => set_menu(Menu::Journal, Scene::Journal),
+                            event::KeyCode::Char('q') | event::KeyCode::Char('Q') => set_menu(Menu::Quests, Scene::Quests),
                             event::KeyCode::Char('s') | event::KeyCode::Char('S') => set_menu(Menu::Settings, Scene::Settings),
                             _ => {},
                         }
@@ -380,6 +477,24 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
                                 (*player_input).input_movement = MovementInput::Idle;
                             };
                             match key.code {
+                                // Numpad
+                                event::KeyCode::Char('8') => set_player_input_movement(app, MovementInput::North),
+                                event::KeyCode::Char('6') => set_player_input_movement(app, MovementInput::East),
+                                event::KeyCode::Char('2') => set_player_input_movement(app, MovementInput::South),
+                                event::KeyCode::Char('4') => set_player_input_movement(app, MovementInput::West),
+                                event::KeyCode::Char('9') | event::KeyCode::PageUp => set_player_input_movement(app, MovementInput::NorthEast),
+                                event::KeyCode::Char('3') | event::KeyCode::PageDown => set_player_input_movement(app, MovementInput::SouthEast),
+                                event::KeyCode::Char('1') | event::KeyCode::End => set_player_input_movement(app, MovementInput::SouthWest),
+                                event::KeyCode::Char('7') | event::KeyCode::Home => set_player_input_movement(app, MovementInput::NorthWest),
+                                // Vi-keys, j moves rather than opening the journal, J still does
+                                event::KeyCode::Char('k') if vi_keys => set_player_input_movement(app, MovementInput::North),
+                                event::KeyCode::Char('l') if vi_keys => set_player_input_movement(app, MovementInput::East),
+                                event::KeyCode::Char('j') if vi_keys => set_player_input_movement(app, MovementInput::South),
+                                event::KeyCode::Char('h') if vi_keys => set_player_input_movement(app, MovementInput::West),
+                                event::KeyCode::Char('u') if vi_keys => set_player_input_movement(app, MovementInput::NorthEast),
+                                event::KeyCode::Char('n') if vi_keys => set_player_input_movement(app, MovementInput::SouthEast),
+                                event::KeyCode::Char('b') if vi_keys => set_player_input_movement(app, MovementInput::SouthWest),
+                                event::KeyCode::Char('y') if vi_keys => set_player_input_movement(app, MovementInput::NorthWest),
                                 event::KeyCode::Up => {
                                     let player_state = app.world.resource::<PlayerState>();
                                     match player_state {
@@ -426,8 +541,28 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
                                             if let Some(index) = data.active_option.get_index(dialogue.options.len()) {
                                                 let new_player_state = dialogue.select(copied_player_state, index);
                                                 data.active_option.check(dialogue.options.len());
+                                                let accused = dialogue.accused.take();
                                                 let mut player_state = app.world.resource_mut::<PlayerState>();
                                                 *player_state = new_player_state;
+                                                if let Some(accused) = accused {
+                                                    accuse(app, accused);
+                                                }
+                                                resolve(app);
+                                            }
+                                        },
+                                        PlayerState::Looting if app.world.resource::<LootMenu>().trade.is_some() => {
+                                            let count = match data.active_option.focus {
+                                                Focus::Ours => app.world.query_filtered::<&Inventory, With<PlayerTag>>().get_single(&app.world).ok()
+                                                    .map(|inventory| inventory.items().len()),
+                                                Focus::Other => app.world.resource::<LootMenu>().inventory
+                                                    .and_then(|entity| app.world.get::<Inventory>(entity))
+                                                    .map(|inventory| inventory.items().len()),
+                                            };
+                                            if let Some(index) = count.and_then(|count| data.active_option.get_index(count)) {
+                                                let ours = matches!(data.active_option.focus, Focus::Ours);
+                                                if let Some(trade) = &mut app.world.resource_mut::<LootMenu>().trade {
+                                                    trade.toggle(ours, index);
+                                                }
                                             }
                                         },
                                         PlayerState::Looting => {
@@ -471,6 +606,25 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
                                         }
                                     }
                                 },
+                                event::KeyCode::Char('c') | event::KeyCode::Char('C') => {
+                                    if matches!(app.world.resource::<PlayerState>(), PlayerState::None) {
+                                        let mut action_input = app.world.resource_mut::<ActionInput>();
+                                        *action_input = ActionInput::CloseDoors;
+                                        app.update();
+                                    }
+                                },
+                                event::KeyCode::Char('g') | event::KeyCode::Char('G') => {
+                                    if matches!(app.world.resource::<PlayerState>(), PlayerState::None) {
+                                        let mut action_input = app.world.resource_mut::<ActionInput>();
+                                        *action_input = ActionInput::PickUp;
+                                        app.update();
+                                    }
+                                },
+                                event::KeyCode::Char('t') | event::KeyCode::Char('T') => {
+                                    if matches!(app.world.resource::<PlayerState>(), PlayerState::Looting) && propose(app).is_ok() {
+                                        data.active_option.check_from_focus(&mut app.world);
+                                    }
+                                },
                                 event::KeyCode::Tab => {
                                     // Select other inventory in loot menu!
                                     data.active_option.focus = match data.active_option.focus {
@@ -544,9 +698,26 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
                                         }
                                     }
                                 },
+                                event::KeyCode::Char('d') | event::KeyCode::Char('D') => {
+                                    let camera_data = app.world.resource::<CameraData>();
+                                    if let Some(current_value) = camera_data.inventory_selection.selected() {
+                                        let mut action_input = app.world.resource_mut::<ActionInput>();
+                                        *action_input = ActionInput::DropFromInventory(current_value);
+                                        app.update();
+                                        if let Ok(inventory) = app.world.query_filtered::<&Inventory, With<PlayerTag>>().get_single(&app.world) {
+                                            let count = inventory.items().len();
+                                            if current_value >= count {
+                                                let mut camera_data = app.world.resource_mut::<CameraData>();
+                                                camera_data.inventory_selection.select(count.checked_sub(1));
+                                            }
+                                        }
+                                    }
+                                },
                                 _ => switch_menu(&mut data.active_menu),
                             }
                         },
+                        Menu::Journal |
+                        Menu::Quests => switch_menu(&mut data.active_menu),
                         Menu::Settings => {
                             match key.code {
                                 event::KeyCode::Esc => {
@@ -556,6 +727,14 @@ fn setup_game(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
                                     execute!(std::io::stdout(), crossterm::terminal::LeaveAlternateScreen).ok();
                                     break;
                                 },
+                                event::KeyCode::Char('d') | event::KeyCode::Char('D') => {
+                                    let mut settings = app.world.resource_mut::<Settings>();
+                                    settings.diagonal_movement = !settings.diagonal_movement;
+                                },
+                                event::KeyCode::Char('v') | event::KeyCode::Char('V') => {
+                                    let mut settings = app.world.resource_mut::<Settings>();
+                                    settings.vi_keys = !settings.vi_keys;
+                                },
                                 _ => switch_menu(&mut data.active_menu),
                             }
                         },
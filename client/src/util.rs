@@ -1,4 +1,5 @@
-use common::inventory::Inventory;
+use std::collections::BTreeSet;
+use common::inventory::{Inventory, Item};
 use tui::{widgets::{ListItem, List, Block, Borders}, text::Text};
 
 pub fn render_inventory<'a>(inventory: &'a Inventory, title: &'a str) -> List<'a> {
@@ -10,3 +11,15 @@ pub fn render_inventory<'a>(inventory: &'a Inventory, title: &'a str) -> List<'a
         .block(Block::default().borders(Borders::ALL).title(title))
         .highlight_symbol(">")
 }
+
+/// Lists the items with their worth in trade, marking those put up.
+pub fn render_trade_inventory<'a>(inventory: &Inventory, title: String, marked: &BTreeSet<usize>, worth: impl Fn(&Item) -> u32) -> List<'a> {
+    let mut items = Vec::<ListItem>::with_capacity(inventory.items().len());
+    for (index, item) in inventory.items().iter().enumerate() {
+        let mark = if marked.contains(&index) { "+" } else { " " };
+        items.push(ListItem::new(Text::raw(format!("{mark} {} ({})", item.get_name(), worth(item)))));
+    }
+    List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_symbol(">")
+}
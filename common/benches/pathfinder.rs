@@ -0,0 +1,91 @@
+//! Times `pathfinder_update` with hundreds of characters walking the town.
+//! Run with `cargo bench -p common --bench pathfinder`.
+
+use std::time::{Duration, Instant};
+use bevy::{prelude::*, ecs::system::CommandQueue};
+use common::{
+    physics::{Map, MapCache, Position, Tile},
+    character::npc_movement_update,
+    behaviors::pathfinder::{
+        pathfinder_update, PathfinderBudget, data::PathfinderGlobalData, rooms::RoomGraph,
+        reservations::{Reservations, swap_update}, danger::DangerZones,
+    },
+    noise::Noise,
+    spatial::{SpatialIndex, spatial_index_update},
+    time::Clock,
+    schedule::{Schedules, Routine},
+    map_setup::town,
+    util::spawn_lerain,
+    Settings,
+};
+
+const NPC_COUNTS: [usize; 3] = [100, 300, 600];
+/// Distance between spawned characters.
+const SPACING: usize = 3;
+const WARMUP_TICKS: usize = 5;
+const TICKS: usize = 100;
+
+fn setup_world(npc_count: usize, budget: usize) -> World {
+    let mut world = World::new();
+    world.init_resource::<Map>();
+    world.insert_resource(MapCache::default());
+    world.insert_resource(Noise::default());
+    world.insert_resource(Clock::default());
+    world.insert_resource(Settings::default());
+    world.insert_resource(PathfinderBudget::new(budget));
+    world.insert_resource(RoomGraph::default());
+    world.insert_resource(Reservations::default());
+    world.insert_resource(DangerZones::default());
+    world.init_resource::<PathfinderGlobalData>();
+    world.init_resource::<Schedules>();
+    world.init_resource::<SpatialIndex>();
+
+    let mut queue = CommandQueue::default();
+    world.resource_scope(|world, mut map: Mut<Map>| {
+        let data = world.resource::<PathfinderGlobalData>();
+        let schedules = world.resource::<Schedules>();
+        let mut commands = Commands::new(&mut queue, world);
+        town(&mut commands, &mut map, data, schedules);
+        let mut spawned = 0;
+        'spawn: for y in (0..map.get_size_y()).step_by(SPACING) {
+            for x in (0..map.get_size_x()).step_by(SPACING) {
+                if spawned == npc_count { break 'spawn; }
+                if let Some(Tile::Ground { occupier: None, .. }) = map.get(x, y) {
+                    spawn_lerain(&mut commands, &mut map, "Bench", Position::new(x as i32, y as i32), Routine::default(), Vec::new());
+                    spawned += 1;
+                }
+            }
+        }
+    });
+    queue.apply(&mut world);
+    world
+}
+
+fn run(npc_count: usize, budget: usize) -> Duration {
+    let mut world = setup_world(npc_count, budget);
+    let mut stage = SystemStage::single_threaded()
+        .with_system(spatial_index_update.label("spatial"))
+        .with_system(pathfinder_update.label("pathfinder").after("spatial"))
+        .with_system(swap_update.label("swap").after("pathfinder"))
+        .with_system(npc_movement_update.after("swap"));
+    for _ in 0..WARMUP_TICKS {
+        stage.run(&mut world);
+    }
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        stage.run(&mut world);
+    }
+    start.elapsed() / TICKS as u32
+}
+
+fn main() {
+    for npc_count in NPC_COUNTS {
+        let unlimited = run(npc_count, usize::MAX);
+        let budgeted = run(npc_count, PathfinderBudget::default().per_tick);
+        println!(
+            "{npc_count} characters: {:?} per tick unlimited, {:?} per tick budgeted",
+            unlimited,
+            budgeted,
+        );
+    }
+}
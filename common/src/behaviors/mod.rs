@@ -1,3 +1,4 @@
 pub mod pathfinder;
 pub mod werewolf;
-mod util;
+pub mod rumdare;
+pub(crate) mod util;
@@ -0,0 +1,26 @@
+use crate::physics::Position;
+
+/// Ticks a beast sighting keeps its surroundings dangerous.
+const DANGER_TICKS: u64 = 40;
+/// Squared distance from a sighting considered dangerous.
+const DANGER_DISTANCE: u32 = 8 * 8;
+/// Extra cost of planning through a dangerous tile.
+const DANGER_COST: u32 = 20;
+
+/// Places the beast was seen lately, humans route around them.
+#[derive(Default)]
+pub struct DangerZones {
+    sightings: Vec<(Position, u64)>,
+}
+impl DangerZones {
+    pub fn spot(&mut self, position: Position, tick: u64) {
+        self.sightings.retain(|(p, spotted)| *p != position && tick - spotted < DANGER_TICKS);
+        self.sightings.push((position, tick));
+    }
+    /// Extra cost of walking through the position.
+    pub fn cost(&self, position: &Position, tick: u64) -> u32 {
+        let is_dangerous = self.sightings.iter()
+            .any(|(p, spotted)| tick - spotted < DANGER_TICKS && p.distance(position) <= DANGER_DISTANCE);
+        if is_dangerous { DANGER_COST } else { 0 }
+    }
+}
@@ -1,18 +1,44 @@
 use bevy::prelude::FromWorld;
-use rand::Rng;
+use rand::{distributions::WeightedIndex, prelude::Distribution};
+use serde::Deserialize;
 use tui::symbols::line::TOP_RIGHT;
-use crate::{physics::{Position, Quadrant}, character::CharacterType};
+use crate::{physics::{Map, Position, Quadrant, Tile}, character::CharacterType};
+use super::rooms::RoomGraph;
 use bitflags::bitflags;
 
-// TODO: MAKE OBJECTIVES CLOSER TO
-// NPC MORE LIKELY TO OCCUR!
+#[derive(Deserialize)]
+struct PointsData {
+    interest: Vec<Vec<(i32, i32)>>,
+    krill_exits: Vec<(i32, i32)>,
+    human_hiding: Vec<Vec<(i32, i32)>>,
+    werewolf_hiding: Vec<Vec<(i32, i32)>>,
+}
+
+/// Points of interest, exits and hiding spots, loaded from data.
 pub struct PathfinderGlobalData {
     points: Vec<Vec<Position>>,
-    krill_exit_points: [Position; 3],
+    krill_exit_points: Vec<Position>,
     pub human: HumanPathfinderGlobalData,
     pub werewolf: WerewolfPathfinderGlobalData,
 }
 
+#[derive(Debug)]
+pub enum PointError {
+    /// No points given where some are needed.
+    Empty,
+    NotGround(Position),
+    Unreachable(Position),
+}
+impl std::fmt::Display for PointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PointError::Empty => write!(f, "empty group of points"),
+            PointError::NotGround(p) => write!(f, "point ({}, {}) is not ground", p.x, p.y),
+            PointError::Unreachable(p) => write!(f, "point ({}, {}) cannot be reached", p.x, p.y),
+        }
+    }
+}
+
 fn get_fn<
     'a,
     I,
@@ -35,40 +61,45 @@ fn get_fn<
     }
 }
 
+/// Closer points are more likely to be picked.
+fn weight(point: &Position, from: &Position) -> f32 {
+    1.0 / (1.0 + (point.distance(from) as f32).sqrt())
+}
+/// Random index by weight, none if nothing can be picked.
+fn pick(weights: impl IntoIterator<Item = f32>) -> Option<usize> {
+    WeightedIndex::new(weights).ok().map(|index| index.sample(&mut rand::thread_rng()))
+}
+
 pub struct GetPoint<'a> {
     character_type: CharacterType,
+    /// Where the one looking for a point stands.
+    position: Position,
     points: Vec<&'a Vec<Position>>,
 }
 impl<'a> GetPoint<'a> {
     pub fn get(self) -> (Position, usize) {
-        get_fn(
-            self.points,
-            self.character_type,
-            move |points| {
-                let i0 = rand::thread_rng().gen_range(0..points.len());
-                let i0 = 0; // TO REMOVE
-                let i1 = rand::thread_rng().gen_range(0..points[i0].len());
-                (i0, i1)
-            },
-            move |points, indexes| points[indexes.0][indexes.1],
-        )
+        self.pick(None)
     }
     pub fn get_except(self, exclude_index: usize) -> (Position, usize) {
+        self.pick(Some(exclude_index))
+    }
+    /// Picks a group then a point within it, nearer ones being more likely.
+    /// The excluded group is only picked if there is no other.
+    fn pick(self, exclude_index: Option<usize>) -> (Position, usize) {
+        let position = self.position;
         get_fn(
             self.points,
             self.character_type,
             move |points| {
-                let length = points.len();
-                let mut i0 = rand::thread_rng().gen_range(0..length);
-                if i0 == exclude_index {
-                    if i0 == length - 1 {
-                        i0 = 0;
-                    } else {
-                        i0 += 1;
-                    }
-                }
-                let i0 = 0; // TO REMOVE
-                let i1 = rand::thread_rng().gen_range(0..points[i0].len());
+                let group_weight = |group: &Vec<Position>| group.iter()
+                    .map(|p| weight(p, &position))
+                    .fold(0.0, f32::max);
+                let i0 = pick(points.iter().enumerate().map(|(i, group)| {
+                    if Some(i) == exclude_index { 0.0 } else { group_weight(group) }
+                }))
+                    .or_else(|| pick(points.iter().map(|group| group_weight(group))))
+                    .unwrap_or(0);
+                let i1 = pick(points[i0].iter().map(|p| weight(p, &position))).unwrap_or(0);
                 (i0, i1)
             },
             move |points, indexes| points[indexes.0][indexes.1],
@@ -76,32 +107,31 @@ impl<'a> GetPoint<'a> {
     }
 }
 
-pub struct GetPanicPoint<'a, const T: usize> {
-    points: &'a [Vec<Position>; T],
+pub struct GetPanicPoint<'a> {
+    points: &'a [Vec<Position>],
     friendly: (CharacterType, Position),
 }
-impl<'a, const T: usize> GetPanicPoint<'a, T> {
-    pub fn enemy(self, enemy_position: Position) -> GetPanicPointWithEnemy<'a, T> {
-        GetPanicPointWithEnemy {
-            panic_point: GetPanicPoint {
-                points: self.points,
-                friendly: self.friendly,
-            },
-            enemy_position,
+impl<'a> GetPanicPoint<'a> {
+    pub fn enemies(self, enemies: &[Position]) -> GetPanicPointWithEnemies<'a> {
+        GetPanicPointWithEnemies {
+            panic_point: self,
+            enemies: enemies.to_vec(),
         }
     }
     pub fn get(self) -> (Position, usize) {
-        let character_type = self.friendly.0.clone();
+        let (character_type, position) = self.friendly.clone();
         GetPoint {
             points: self.get_points(),
             character_type,
+            position,
         }.get()
     }
     pub fn get_except(self, exclude_index: usize) -> (Position, usize) {
-        let character_type = self.friendly.0.clone();
+        let (character_type, position) = self.friendly.clone();
         GetPoint {
             points: self.get_points(),
             character_type,
+            position,
         }.get_except(exclude_index)
     }
     fn get_points(self) -> Vec<&'a Vec<Position>> {
@@ -118,57 +148,85 @@ impl<'a, const T: usize> GetPanicPoint<'a, T> {
     }
 }
 
-pub struct GetPanicPointWithEnemy<'a, const T: usize> {
-    panic_point: GetPanicPoint<'a, T>,
-    enemy_position: Position,
+/// Squared distance kept from enemies when running past them.
+const ENEMY_CLEARANCE: f32 = 4.0 * 4.0;
+
+pub struct GetPanicPointWithEnemies<'a> {
+    panic_point: GetPanicPoint<'a>,
+    enemies: Vec<Position>,
 }
-impl<'a, const T: usize> GetPanicPointWithEnemy<'a, T> {
+impl<'a> GetPanicPointWithEnemies<'a> {
     pub fn get(self) -> (Position, usize) {
-        let character_type = self.panic_point.friendly.0.clone();
-        GetPoint {
-            points: self.get_points(),
-            character_type,
-        }.get()
+        self.choose(None)
     }
     pub fn get_except(self, exclude_index: usize) -> (Position, usize) {
-        let character_type = self.panic_point.friendly.0.clone();
-        GetPoint {
-            points: self.get_points(),
-            character_type,
-        }.get_except(exclude_index)
+        self.choose(Some(exclude_index))
     }
-    fn get_points(self) -> Vec<&'a Vec<Position>> {
+    /// 1. Nearest point in the quadrant opposite the enemies.
+    /// 2. Otherwise the point furthest from the enemies in the remaining quadrants free of enemies.
+    /// 3. Otherwise the point furthest from the enemies.
+    /// Points we would have to run past an enemy to reach are left for the last step.
+    fn choose(self, exclude_index: Option<usize>) -> (Position, usize) {
         let friendly_position = self.panic_point.friendly.1;
-        let quadrant = friendly_position.quadrant(self.enemy_position);
-        let optimal_quadrant = quadrant.opposite();
-        let goal = self.panic_point.points.into_iter()
-            .find(|points| {
-                let goal = points[0];
-                friendly_position.quadrant(goal).contains(optimal_quadrant)
-            });
-        if let Some(goal) = goal {
-            vec![goal]
-        } else {
-            self.panic_point.points.into_iter()
-                .max_by(|v1, v2| {
-                    let point1 = v1[0];
-                    let point2 = v2[0];
-                    let dis1 = point1.distance(&self.enemy_position);
-                    let dis2 = point2.distance(&self.enemy_position);
-                    dis1.cmp(&dis2)
-                })
-                .into_iter()
-                .collect()
+        let enemies = &self.enemies;
+        let mut points: Vec<(Position, usize)> = self.panic_point.points.iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != exclude_index)
+            .flat_map(|(i, group)| group.iter().map(move |p| (*p, i)))
+            .collect();
+        if points.is_empty() {
+            points = self.panic_point.points.iter()
+                .enumerate()
+                .flat_map(|(i, group)| group.iter().map(move |p| (*p, i)))
+                .collect();
         }
+
+        let enemy_quadrants = enemies.iter()
+            .fold(Quadrant::empty(), |quadrant, enemy| quadrant | friendly_position.quadrant(*enemy));
+        let optimal_quadrant = enemies.iter()
+            .fold(Quadrant::all(), |quadrant, enemy| quadrant & friendly_position.quadrant(*enemy).opposite());
+        let remaining_quadrant = !enemy_quadrants & !optimal_quadrant;
+        let in_quadrant = |point: &Position, quadrant: Quadrant| {
+            let point_quadrant = friendly_position.quadrant(*point);
+            point_quadrant.intersects(quadrant) && !point_quadrant.intersects(enemy_quadrants)
+        };
+        let enemy_distance = |point: &Position| enemies.iter()
+            .map(|enemy| enemy.distance(point))
+            .min()
+            .unwrap_or(u32::MAX);
+        let safe = || points.iter().filter(|(p, _)| !passes_near(&friendly_position, p, enemies));
+
+        safe()
+            .filter(|(p, _)| in_quadrant(p, optimal_quadrant))
+            .min_by_key(|(p, _)| p.distance(&friendly_position))
+            .or_else(|| safe()
+                .filter(|(p, _)| in_quadrant(p, remaining_quadrant))
+                .max_by_key(|(p, _)| enemy_distance(p)))
+            .or_else(|| points.iter().max_by_key(|(p, _)| enemy_distance(p)))
+            .cloned()
+            .unwrap_or((friendly_position, 0))
     }
 }
 
+/// Does running straight from one position to the other pass close to an enemy?
+fn passes_near(from: &Position, to: &Position, enemies: &[Position]) -> bool {
+    let (dx, dy) = ((to.x - from.x) as f32, (to.y - from.y) as f32);
+    let length = dx * dx + dy * dy;
+    enemies.iter().any(|enemy| {
+        let (ex, ey) = ((enemy.x - from.x) as f32, (enemy.y - from.y) as f32);
+        // Closest point along the way.
+        let t = if length == 0.0 { 0.0 } else { ((ex * dx + ey * dy) / length).clamp(0.0, 1.0) };
+        let (cx, cy) = (ex - t * dx, ey - t * dy);
+        cx * cx + cy * cy <= ENEMY_CLEARANCE
+    })
+}
+
 pub struct HumanPathfinderGlobalData {
-    hiding_points: [Vec<Position>; 2],
+    hiding_points: Vec<Vec<Position>>,
 }
 
 impl HumanPathfinderGlobalData {
-    pub fn panic(&self, friendly: (CharacterType, Position)) -> GetPanicPoint<2> {
+    pub fn panic(&self, friendly: (CharacterType, Position)) -> GetPanicPoint {
         GetPanicPoint {
             points: &self.hiding_points,
             friendly,
@@ -177,11 +235,11 @@ impl HumanPathfinderGlobalData {
 }
 
 pub struct WerewolfPathfinderGlobalData {
-    hiding_points: [Vec<Position>; 2],
+    hiding_points: Vec<Vec<Position>>,
 }
 
 impl WerewolfPathfinderGlobalData {
-    pub fn panic(&self, friendly: (CharacterType, Position)) -> GetPanicPoint<2> {
+    pub fn panic(&self, friendly: (CharacterType, Position)) -> GetPanicPoint {
         GetPanicPoint {
             points: &self.hiding_points,
             friendly,
@@ -190,9 +248,43 @@ impl WerewolfPathfinderGlobalData {
 }
 
 impl PathfinderGlobalData {
-    pub fn target(&self, character_type: CharacterType) -> GetPoint {
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        let data = ron::from_str::<PointsData>(text)?;
+        let to_positions = |points: Vec<(i32, i32)>| points.into_iter().map(|(x, y)| Position::new(x, y)).collect::<Vec<_>>();
+        let to_groups = |groups: Vec<Vec<(i32, i32)>>| groups.into_iter().map(to_positions).collect();
+        Ok(PathfinderGlobalData {
+            points: to_groups(data.interest),
+            krill_exit_points: to_positions(data.krill_exits),
+            human: HumanPathfinderGlobalData { hiding_points: to_groups(data.human_hiding) },
+            werewolf: WerewolfPathfinderGlobalData { hiding_points: to_groups(data.werewolf_hiding) },
+        })
+    }
+    /// Checks every point is ground reachable from the first point of interest.
+    pub fn validate(&self, map: &Map) -> Result<(), PointError> {
+        let groups = || self.points.iter()
+            .chain(self.human.hiding_points.iter())
+            .chain(self.werewolf.hiding_points.iter());
+        if self.points.is_empty() || groups().any(Vec::is_empty) {
+            return Err(PointError::Empty);
+        }
+        let mut room_graph = RoomGraph::default();
+        room_graph.refresh(map);
+        let origin = self.points[0][0];
+        for point in groups().flatten().chain(self.krill_exit_points.iter()) {
+            if !matches!(point.get_from_map(map), Some(Tile::Ground { .. })) {
+                return Err(PointError::NotGround(*point));
+            }
+            if !room_graph.is_connected(&origin, point) {
+                return Err(PointError::Unreachable(*point));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn target(&self, character_type: CharacterType, position: Position) -> GetPoint {
         GetPoint {
             character_type,
+            position,
             points: self.points.iter().collect(),
         }
     }
@@ -222,47 +314,6 @@ impl PathfinderGlobalData {
 
 impl FromWorld for PathfinderGlobalData {
     fn from_world(_world: &mut bevy::prelude::World) -> Self {
-        let points = vec![
-            vec![
-                Position::new(110, 42),
-                Position::new(69, 10),
-                Position::new(151, 10),
-            ],
-            vec![
-                Position::new(3, 3),
-                Position::new(80, 60),
-            ],
-        ];
-        let krill_exit_points = [
-            Position::new(111, 42),
-            Position::new(68, 10),
-            Position::new(150, 10),
-        ];
-        let human = HumanPathfinderGlobalData {
-            hiding_points: [
-                vec![
-                    Position::new(30, 30),
-                ],
-                vec![
-                    Position::new(201, 73),
-                ],
-            ]
-        };
-        let werewolf = WerewolfPathfinderGlobalData {
-            hiding_points: [
-                vec![
-                    Position::new(201, 73),
-                ],
-                vec![
-                    Position::new(30, 30),
-                ],
-            ],
-        };
-        PathfinderGlobalData {
-            points,
-            krill_exit_points,
-            human,
-            werewolf,
-        }
+        PathfinderGlobalData::from_ron(include_str!("../../../data/points.ron")).expect("valid points data")
     }
 }
@@ -1,10 +1,10 @@
-use bevy::prelude::Query;
+use rand::seq::SliceRandom;
 use crate::{
-    physics::{Map, MapCache, Position, KrillTheaterZone, Tile, Occupier},
+    physics::{Position, KrillTheaterZone},
     character::{CharacterType, CharacterData},
-    map_brain::{CharacterBehaviorData, HumanState, NewObjective}, behaviors::util::{human_panic, set_human_panic},
+    map_brain::{CharacterBehaviorData, HumanState, NewObjective}, behaviors::util::{human_panic, human_hear, set_human_panic},
 };
-use super::{PathfinderBehavior, util::get_pathfinder_target, data::PathfinderGlobalData, Priority};
+use super::{PathfinderBehavior, TargetParams, util::get_pathfinder_target, Priority};
 
 fn set_goal(state: &mut HumanState, behavior: &mut PathfinderBehavior, goal: (Position, usize), priority: Priority) {
     *state = HumanState::Moving(goal.1);
@@ -18,60 +18,79 @@ fn set_goal(state: &mut HumanState, behavior: &mut PathfinderBehavior, goal: (Po
 }
 
 pub fn lerain_pathfinder(
-    data: &PathfinderGlobalData,
     behavior: &mut PathfinderBehavior,
-    map: &Map,
-    map_cache: &mut MapCache,
-    character_type: &CharacterType,
-    character_data: &mut CharacterData,
+    _character_data: &mut CharacterData,
     character_behavior_data: &mut CharacterBehaviorData,
-    position: &Position,
-    query: &Query<(&CharacterType, &Position)>,
+    params: TargetParams,
 ) {
     if let CharacterBehaviorData::Human { human_state: state } = character_behavior_data {
-        human_pathfinder(
-            character_type,
-            state,
-            data,
-            behavior,
-            map,
-            map_cache,
-            position,
-            query,
-            CharacterType::Werewolf,
-        );
+        human_pathfinder(state, behavior, params, CharacterType::Werewolf);
     }
 }
 
 pub fn human_pathfinder(
-    character_type: &CharacterType,
     state: &mut HumanState,
-    data: &PathfinderGlobalData,
     behavior: &mut PathfinderBehavior,
-    map: &Map,
-    map_cache: &mut MapCache,
-    position: &Position,
-    query: &Query<(&CharacterType, &Position)>,
+    params: TargetParams,
     target_character_type: CharacterType,
 ) {
+    let TargetParams { data, map, map_cache, noise, clock, character_type, position, routine, query, spatial_index, .. } = params;
+    if matches!(state, HumanState::Idle(_) | HumanState::Moving(_) | HumanState::Routine(_))
+        && matches!(character_type, CharacterType::Lerain | CharacterType::Rumdare) {
+        if let Some(origin) = human_hear(noise, position) {
+            // Something terrible happened nearby, run!
+            set_human_panic(data, behavior, state, (character_type.clone(), *position), &[origin]);
+            return;
+        }
+    }
+    if let Some(routine) = routine {
+        let scheduled = routine.at(clock.hour());
+        match state {
+            HumanState::Idle(_) | HumanState::Moving(_) => if let Some((index, entry)) = scheduled {
+                // Time to be somewhere.
+                if let Some(goal) = entry.places.choose(&mut rand::thread_rng()) {
+                    behavior.set_goal(*goal, Priority::Medium);
+                    *state = HumanState::Routine(index);
+                    return;
+                }
+            },
+            HumanState::Routine(index) => if scheduled.map(|(i, _)| i) != Some(*index) {
+                // Done here, find something else to do.
+                behavior.set_goal(*position, Priority::Low);
+                *state = HumanState::Idle(None);
+                return;
+            },
+            HumanState::Panic(_) => {},
+        }
+    }
     match state {
         HumanState::Idle(objective) => {
+            if matches!(character_type, CharacterType::Lerain) {
+                let enemies = human_panic(map, map_cache, *position, clock.vision_distance());
+                if !enemies.is_empty() {
+                    // Do not wander towards a werewolf, run!
+                    set_human_panic(data, behavior, state, (character_type.clone(), *position), &enemies);
+                    return;
+                }
+            }
             if !get_pathfinder_target(
                 behavior,
                 map,
                 map_cache,
                 position,
+                clock.vision_distance(),
                 query,
+                spatial_index,
                 target_character_type,
             ) && behavior.is_at(position.clone()) {
-                if let Some(tile) = map.get(position.x as usize, position.y as usize) {
-                    if let Some(krill_theater) = tile.krill_theater() {
+                if let Some(zone) = map.zone_at(position) {
+                    if let Some(krill_theater) = zone.krill_theater() {
                         match krill_theater {
                             KrillTheaterZone::Free => {
                                 set_goal(
                                     state,
                                     behavior,
-                                    data.target(CharacterType::Lerain).get(),
+                                    data.target(CharacterType::Lerain, *position).get(),
                                     Priority::Low,
                                 );
                             },
@@ -89,7 +108,7 @@ pub fn human_pathfinder(
                                         set_goal(
                                             state,
                                             behavior,
-                                            data.target(CharacterType::Lerain).get_except(index),
+                                            data.target(CharacterType::Lerain, *position).get_except(index),
                                             Priority::Medium,
                                         );
                                     }
@@ -97,7 +116,7 @@ pub fn human_pathfinder(
                                     set_goal(
                                         state,
                                         behavior,
-                                        data.target(CharacterType::Lerain).get(),
+                                        data.target(CharacterType::Lerain, *position).get(),
                                         Priority::Medium,
                                     );
                                 }
@@ -106,7 +125,7 @@ pub fn human_pathfinder(
                     } else if let Some(o) = objective {
                         match o {
                             NewObjective::WanderButExclude(index) => {
-                                let goal = data.target(CharacterType::Lerain).get_except(*index);
+                                let goal = data.target(CharacterType::Lerain, *position).get_except(*index);
                                 set_goal(state, behavior, goal, Priority::Low);
                             },
                         }
@@ -114,7 +133,7 @@ pub fn human_pathfinder(
                         set_goal(
                             state,
                             behavior,
-                            data.target(CharacterType::Lerain).get(),
+                            data.target(CharacterType::Lerain, *position).get(),
                             Priority::Low,
                         );
                     }
@@ -125,14 +144,27 @@ pub fn human_pathfinder(
             match character_type {
                 CharacterType::Player => {},
                 CharacterType::Lerain | CharacterType::Rumdare => {
-                    if let Some((_, target)) = human_panic(map, map_cache, *position) {
+                    let enemies = human_panic(map, map_cache, *position, clock.vision_distance());
+                    if !enemies.is_empty() {
                         // If werewolf is in sight, panic!
-                        set_human_panic(data, behavior, state, (character_type.clone(), *position), target);
+                        set_human_panic(data, behavior, state, (character_type.clone(), *position), &enemies);
                     }
                 },
                 CharacterType::Werewolf => { /* TODO */},
             }
         },
+        HumanState::Routine(_) => {
+            match character_type {
+                CharacterType::Player => {},
+                CharacterType::Lerain | CharacterType::Rumdare => {
+                    let enemies = human_panic(map, map_cache, *position, clock.vision_distance());
+                    if !enemies.is_empty() {
+                        set_human_panic(data, behavior, state, (character_type.clone(), *position), &enemies);
+                    }
+                },
+                CharacterType::Werewolf => {},
+            }
+        },
         HumanState::Panic(index) => {
             // TODO, CREATE PANIC BEHAVIOR FOR HUMANS (or when werewolf is in its HUMAN FORM)!
             if behavior.is_at(*position) {
@@ -150,9 +182,10 @@ pub fn human_pathfinder(
                 match character_type {
                     CharacterType::Player => {},
                     CharacterType::Lerain | CharacterType::Rumdare => {
-                        if let Some((_, target)) = human_panic(map, map_cache, *position) {
+                        let enemies = human_panic(map, map_cache, *position, clock.vision_distance());
+                        if !enemies.is_empty() {
                             // We have yet to reach our goal, but we spot a werewolf!
-                            set_human_panic(data, behavior, state, (character_type.clone(), *position), target);
+                            set_human_panic(data, behavior, state, (character_type.clone(), *position), &enemies);
                         }
                     },
                     CharacterType::Werewolf => {},
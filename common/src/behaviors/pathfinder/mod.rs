@@ -3,16 +3,38 @@ pub mod lerain;
 pub mod rumdare;
 pub mod werewolf;
 pub mod data;
+pub mod rooms;
+pub mod reservations;
+pub mod danger;
 
-use bevy::prelude::{Query, Res, ResMut};
+use std::collections::HashSet;
+use bevy::prelude::{Component, Entity, Query, Res, ResMut, Without};
 use pathfinding::prelude::astar;
 use crate::{
-    physics::{Map, Position, Collision, Tile, CollisionType, MapCache},
-    character::{CharacterType, CharacterData, MovementInput},
-    map_brain::{BehaviorData, CharacterBehaviorData},
+    physics::{Map, Position, Collision, Tile, CollisionType, MapCache, Occupier},
+    character::{CharacterType, CharacterData, MovementInput, TerrainDelay, WereForm, Sprite},
+    door::Door,
+    level::Dormant,
+    map_brain::{BehaviorData, CharacterBehaviorData, HumanState},
+    noise::Noise,
+    time::Clock,
+    schedule::{Routine, Patrol},
+    spatial::SpatialIndex,
+    Settings,
 };
 
-use self::data::PathfinderGlobalData;
+use self::{data::PathfinderGlobalData, rooms::RoomGraph, reservations::Reservations, danger::DangerZones};
+
+/// Steps ahead checked for obstructions before reusing a path.
+const PATH_LOOKAHEAD: usize = 2;
+/// Full path searches allowed per tick by default.
+const DEFAULT_PATHFINDER_BUDGET: usize = 64;
+/// Extra cost of planning through a tile someone stands on.
+const CROWD_COST: u32 = 4;
+/// Extra cost of planning through a closed door.
+const DOOR_COST: u32 = 2;
+/// Ticks spent waiting on others before looking for a way around them.
+const STUCK_TICKS: u32 = 5;
 
 #[derive(Default)]
 struct Pathfinder {
@@ -20,6 +42,67 @@ struct Pathfinder {
     last_goal: Position,
     last_path: Vec<Position>,
     path_index: usize,
+    /// Ticks spent waiting on others.
+    stuck_ticks: u32,
+}
+impl Pathfinder {
+    /// Are we standing where the last path expects us?
+    fn is_on_path(&self, position: &Position) -> bool {
+        self.path_index != 0 && self.last_path.get(self.path_index - 1) == Some(position)
+    }
+    /// Are the next few steps of the last path free to walk?
+    /// Others standing in the way will move, so they do not count.
+    fn is_path_clear(&self, collision_query: &mut Query<&mut Collision>, map: &Map) -> bool {
+        self.last_path.iter()
+            .skip(self.path_index)
+            .take(PATH_LOOKAHEAD)
+            .all(|p| *p == self.last_goal || p.is_neighbor(collision_query, map) || p.has_character(map) || p.has_door(map))
+    }
+}
+
+/// Walks along with someone, unless too scared to.
+#[derive(Component)]
+pub struct Follow(pub Entity);
+
+/// Caps how many full path searches run each tick,
+/// characters over budget keep walking their old path.
+pub struct PathfinderBudget {
+    pub per_tick: usize,
+    spent: usize,
+    /// Place in line searching starts from.
+    start: usize,
+    /// Place in line of the first left waiting this tick.
+    refused: Option<usize>,
+}
+impl Default for PathfinderBudget {
+    fn default() -> Self {
+        PathfinderBudget::new(DEFAULT_PATHFINDER_BUDGET)
+    }
+}
+impl PathfinderBudget {
+    pub fn new(per_tick: usize) -> Self {
+        PathfinderBudget { per_tick, spent: 0, start: 0, refused: None }
+    }
+    /// Starts a tick with that many in line, returning where the line starts.
+    /// Whoever was first left waiting last tick goes first this time.
+    fn reset(&mut self, count: usize) -> usize {
+        self.spent = 0;
+        if let Some(refused) = self.refused.take() {
+            self.start = refused;
+        }
+        self.start = if count == 0 { 0 } else { self.start % count };
+        self.start
+    }
+    /// Spends one search for the place in line, false if none are left this tick.
+    fn spend(&mut self, place: usize) -> bool {
+        if self.spent < self.per_tick {
+            self.spent += 1;
+            true
+        } else {
+            self.refused.get_or_insert(place);
+            false
+        }
+    }
 }
 
 struct SkipTurn {
@@ -49,16 +132,25 @@ pub struct ReachedGoalParams<'a> {
     pub character_behavior_data: &'a mut CharacterBehaviorData,
     pub position: &'a Position,
 }
+/// Shared context handed to every pathfinder target function.
+pub struct TargetParams<'a, 'w, 's> {
+    pub data: &'a PathfinderGlobalData,
+    pub map: &'a Map,
+    pub map_cache: &'a mut MapCache,
+    pub noise: &'a Noise,
+    pub clock: &'a Clock,
+    pub character_type: &'a CharacterType,
+    pub position: &'a Position,
+    pub routine: Option<&'a Routine>,
+    pub patrol: Option<&'a Patrol>,
+    pub query: &'a Query<'w, 's, (&'static CharacterType, &'static Position), Without<Dormant>>,
+    pub spatial_index: &'a SpatialIndex,
+}
 type GetTarget = fn(
-    &PathfinderGlobalData,
     &mut PathfinderBehavior,
-    &Map,
-    &mut MapCache,
-    &CharacterType,
     &mut CharacterData,
     &mut CharacterBehaviorData,
-    &Position,
-    &Query<(&CharacterType, &Position)>,
+    TargetParams,
 );
 type ReachedGoal = fn(ReachedGoalParams);
 pub struct PathfinderBehavior {
@@ -120,11 +212,42 @@ impl PathfinderBehavior {
 }
 
 impl Position {
-    fn successors(&self, collision_query: &mut Query<&mut Collision>, map: &Map, target: &Position) -> Vec<(Position, u32)> {
-        self.neighbors(collision_query, map, target).into_iter().map(|p| (p, 1)).collect()
+    /// Walkable neighbors and their cost,
+    /// unless avoiding others their tiles are walkable at a cost.
+    fn successors(&self, collision_query: &mut Query<&mut Collision>, map: &Map, target: &Position, step_rules: &StepRules) -> Vec<(Position, u32)> {
+        let directions = MovementInput::directions(step_rules.diagonal);
+        let mut successors = Vec::with_capacity(directions.len());
+        for offset in directions.iter().filter_map(|direction| direction.to_position().ok()) {
+            if map.cuts_corner(self, &offset) { continue; }
+            let p = *self + offset;
+            let cost = if p == *target || p.is_neighbor(collision_query, map) {
+                0
+            } else if p.has_door(map) {
+                if step_rules.breaks_doors || !step_rules.locked_doors.contains(&p) {
+                    DOOR_COST
+                } else {
+                    continue;
+                }
+            } else if !step_rules.avoid_characters && p.has_character(map) {
+                CROWD_COST
+            } else {
+                continue;
+            };
+            let terrain = map.path_cost(&p);
+            let danger = step_rules.danger.map_or(0, |(danger_zones, tick)| danger_zones.cost(&p, tick));
+            successors.push((p, terrain + cost + danger));
+        }
+        successors
+    }
+    fn has_character(&self, map: &Map) -> bool {
+        self.get_from_map(map).map_or(false, Tile::is_character)
+    }
+    /// Is there a closed door here?
+    fn has_door(&self, map: &Map) -> bool {
+        matches!(self.get_from_map(map), Some(Tile::Ground { occupier: Some(Occupier { sprite: Sprite::Door, .. }), .. }))
     }
     fn is_neighbor(&self, collision_query: &mut Query<&mut Collision>, map: &Map) -> bool {
-        if let Some(tile) = map.get(self.x as usize, self.y as usize) {
+        if let Some(tile) = self.get_from_map(map) {
             match tile {
                 Tile::Ground { occupier, .. } | Tile::Obstacle { occupier } => {
                     if let Some(occupier) = occupier {
@@ -140,19 +263,6 @@ impl Position {
             false
         }
     }
-    fn neighbors(&self, collision_query: &mut Query<&mut Collision>, map: &Map, target: &Position) -> Vec<Position> {
-        let mut neighbors = Vec::<Position>::with_capacity(4);
-        let mut add_to_neighbors = |position: Position| {
-            if position.is_neighbor(collision_query, map) || position == *target {
-                neighbors.push(position);
-            }
-        };
-        add_to_neighbors(Position::new(self.x, self.y + 1));
-        add_to_neighbors(Position::new(self.x + 1, self.y));
-        add_to_neighbors(Position::new(self.x, self.y - 1));
-        add_to_neighbors(Position::new(self.x - 1, self.y));
-        neighbors
-    }
     /// Squared distance. Use for comparing!
     pub fn distance(&self, position: &Position) -> u32 {
         let diff = position - self;
@@ -163,19 +273,50 @@ impl Position {
 pub fn pathfinder_update(
     map: Res<Map>,
     mut map_cache: ResMut<MapCache>,
+    noise: Res<Noise>,
+    clock: Res<Clock>,
     pathfinder_global_data: Res<PathfinderGlobalData>,
+    mut budget: ResMut<PathfinderBudget>,
+    mut room_graph: ResMut<RoomGraph>,
+    mut reservations: ResMut<Reservations>,
+    danger_zones: Res<DangerZones>,
+    settings: Res<Settings>,
+    spatial_index: Res<SpatialIndex>,
     mut query: Query<(
+        Entity,
         &mut BehaviorData<PathfinderBehavior>,
         &CharacterType,
         &mut CharacterData,
         &mut CharacterBehaviorData,
         &Position,
         &mut MovementInput,
-    )>,
+        Option<&Routine>,
+        Option<&Patrol>,
+        Option<&TerrainDelay>,
+        Option<&Follow>,
+    ), Without<Dormant>>,
     mut collision_query: Query<&mut Collision>,
-    search_query: Query<(&CharacterType, &Position)>,
+    search_query: Query<(&'static CharacterType, &'static Position), Without<Dormant>>,
+    door_query: Query<(&Door, &Position), Without<Dormant>>,
 ) {
-    for (mut pathfinder, character_type, mut character_data, mut character_behavior_data, position, mut movement_input) in query.iter_mut() {
+    room_graph.refresh(&map);
+    reservations.clear();
+    let locked_doors: HashSet<Position> = door_query.iter()
+        .filter(|(door, _)| !door.open && !door.is_passable(clock.tick()))
+        .map(|(_, position)| *position)
+        .collect();
+    let entities = query.iter().map(|(entity, ..)| entity).collect::<Vec<_>>();
+    let start = budget.reset(entities.len());
+    for place in (start..entities.len()).chain(0..start) {
+        let (entity, mut pathfinder, character_type, mut character_data, mut character_behavior_data, position, mut movement_input, routine, patrol, terrain_delay, follow) = match query.get_mut(entities[place]) {
+            Ok(item) => item,
+            Err(_) => continue,
+        };
+        if !map.is_active(position) {
+            // Too far from the player to be worth simulating.
+            *movement_input = MovementInput::Idle;
+            continue;
+        }
         if pathfinder.behavior.is_at(position.clone()) {
             // We have reached our goal,
             // forget the path whence we came.
@@ -190,49 +331,90 @@ pub fn pathfinder_update(
                 pathfinder.behavior.reached_goal = None;
             }
         }
+        if terrain_delay.map_or(false, TerrainDelay::is_delayed) {
+            // Still crossing rough ground.
+            *movement_input = MovementInput::Idle;
+            continue;
+        }
         *movement_input = if pathfinder.check_conditions() {
             if pathfinder.behavior.skip_turn.check() {
                 (pathfinder.behavior.target)(
-                    &pathfinder_global_data,
                     &mut pathfinder.behavior,
-                    &map,
-                    &mut map_cache,
-                    character_type,
                     &mut character_data,
                     &mut character_behavior_data,
-                    position,
-                    &search_query,
+                    TargetParams {
+                        data: &pathfinder_global_data,
+                        map: &map,
+                        map_cache: &mut map_cache,
+                        noise: &noise,
+                        clock: &clock,
+                        character_type,
+                        position,
+                        routine,
+                        patrol,
+                        query: &search_query,
+                        spatial_index: &spatial_index,
+                    },
                 );
-                let mut pathfinder = &mut pathfinder.behavior.pathfinder;
-                // Calculate path.
-                if let Some((path, _)) = astar(
-                    position,
-                    |p| p.successors(&mut collision_query, &map, &pathfinder.current_goal),
-                    |p| p.distance(&pathfinder.current_goal),
-                    |p| *p == pathfinder.current_goal,
-                ) {
-                    pathfinder.last_path = path;
-                    pathfinder.path_index = 1;
-                    pathfinder.last_goal = pathfinder.current_goal;
+                let is_panicking = matches!(
+                    *character_behavior_data,
+                    CharacterBehaviorData::Human { human_state: HumanState::Panic(_) } |
+                    CharacterBehaviorData::Werewolf { human_state: HumanState::Panic(_), .. }
+                );
+                if let Some(goal) = follow.filter(|_| !is_panicking).and_then(|Follow(leader)| spatial_index.position_of(*leader)) {
+                    pathfinder.behavior.set_goal(goal, Priority::High);
                 }
-
-                if let Some(target) = pathfinder.last_path.get(pathfinder.path_index) {
-                    pathfinder.path_index += 1;
-                    if target.y > position.y { 
-                        // UP
-                        MovementInput::North
-                    } else if target.x > position.x {
-                        // RIGHT
-                        MovementInput::East
-                    } else if target.y < position.y {
-                        // DOWN
-                        MovementInput::South
-                    } else if target.x < position.x {
-                        // LEFT
-                        MovementInput::West
+                let mut pathfinder = &mut pathfinder.behavior.pathfinder;
+                // Reuse the last path unless the goal moved or the way is blocked.
+                let is_path_valid = pathfinder.is_on_path(position)
+                    && pathfinder.is_path_clear(&mut collision_query, &map);
+                // Waited too long on others, find a way around them.
+                let is_stuck = pathfinder.stuck_ticks >= STUCK_TICKS;
+                let can_follow = if is_path_valid && pathfinder.last_goal == pathfinder.current_goal && !is_stuck {
+                    true
+                } else if budget.spend(place) {
+                    if is_stuck {
+                        pathfinder.stuck_ticks = 0;
+                    }
+                    // Calculate path.
+                    let step_rules = StepRules {
+                        diagonal: settings.diagonal_movement,
+                        avoid_characters: is_stuck,
+                        locked_doors: &locked_doors,
+                        breaks_doors: matches!(*character_data, CharacterData::Werewolf { form: WereForm::Beast }),
+                        danger: matches!(character_type, CharacterType::Lerain).then(|| (&*danger_zones, clock.tick())),
+                    };
+                    if let Some(path) = find_path(position, &pathfinder.current_goal, &room_graph, &mut collision_query, &map, &step_rules) {
+                        pathfinder.last_path = path;
+                        pathfinder.path_index = 1;
+                        pathfinder.last_goal = pathfinder.current_goal;
+                        true
                     } else {
-                        MovementInput::Idle
+                        is_path_valid
+                    }
+                } else {
+                    // Out of budget, keep on the old path for now.
+                    is_path_valid
+                };
+
+                let target = pathfinder.last_path.get(pathfinder.path_index).filter(|_| can_follow).cloned();
+                // Walking into the goal is how we reach whoever stands on it.
+                let is_blocked = |target: &Position, reservations: &mut Reservations| {
+                    *target != pathfinder.current_goal
+                        && (target.has_character(&map) || !reservations.reserve(entity, *target))
+                };
+                if let Some(target) = target.filter(|target| is_blocked(target, &mut reservations)) {
+                    // Someone is in the way, wait for them or ask to swap.
+                    reservations.want(entity, *position, target);
+                    pathfinder.stuck_ticks += 1;
+                    MovementInput::Idle
+                } else if let Some(target) = target {
+                    // Bumping into a door opens it, step through next time.
+                    if !target.has_door(&map) {
+                        pathfinder.path_index += 1;
                     }
+                    pathfinder.stuck_ticks = 0;
+                    MovementInput::from_offset(target - *position)
                 } else {
                     MovementInput::Idle
                 }
@@ -245,3 +427,63 @@ pub fn pathfinder_update(
         };
     }
 }
+
+/// How whoever plans the path may step and what makes a step more expensive.
+struct StepRules<'a> {
+    diagonal: bool,
+    avoid_characters: bool,
+    /// Closed doors that will not open for us.
+    locked_doors: &'a HashSet<Position>,
+    breaks_doors: bool,
+    /// Dangerous places and the current tick, for those who fear the beast.
+    danger: Option<(&'a DangerZones, u64)>,
+}
+
+/// Fewest steps between the positions, no step costs less than one
+/// so this never overestimates the cost of a path.
+fn steps(from: &Position, to: &Position, diagonal: bool) -> u32 {
+    let (dx, dy) = ((from.x - to.x).abs(), (from.y - to.y).abs());
+    if diagonal { dx.max(dy) as u32 } else { (dx + dy) as u32 }
+}
+
+/// Routes through the rooms first and searches each leg within its room.
+/// A leg failing means something in its room is in the way,
+/// so search the whole map instead.
+fn find_path(
+    start: &Position,
+    goal: &Position,
+    room_graph: &RoomGraph,
+    collision_query: &mut Query<&mut Collision>,
+    map: &Map,
+    step_rules: &StepRules,
+) -> Option<Vec<Position>> {
+    let mut local_path = |from: &Position, to: &Position, room: Option<usize>| {
+        astar(
+            from,
+            |p| {
+                let mut successors = p.successors(collision_query, map, to, step_rules);
+                if room.is_some() {
+                    successors.retain(|(s, _)| s == to || room_graph.room_at(s) == room);
+                }
+                successors
+            },
+            |p| steps(p, to, step_rules.diagonal),
+            |p| p == to,
+        ).map(|(path, _)| path)
+    };
+    if let Some(waypoints) = room_graph.route(start, goal) {
+        let mut path = vec![*start];
+        let mut from = *start;
+        for to in waypoints.iter().chain(std::iter::once(goal)) {
+            if *to == from { continue; }
+            match local_path(&from, to, room_graph.room_at(&from)) {
+                Some(leg) => path.extend(leg.into_iter().skip(1)),
+                None => return local_path(start, goal, None),
+            }
+            from = *to;
+        }
+        Some(path)
+    } else {
+        local_path(start, goal, None)
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::{HashMap, HashSet};
+use bevy::prelude::{Entity, Query, Res, ResMut};
+use crate::{
+    physics::{Map, Position, Tile, Occupier},
+    character::MovementInput,
+    map_brain::BehaviorData,
+};
+use super::PathfinderBehavior;
+
+/// Steps characters plan to take this tick, so they do not walk into each other.
+#[derive(Default)]
+pub struct Reservations {
+    /// Tiles claimed by characters stepping onto them.
+    tiles: HashMap<Position, Entity>,
+    /// Steps blocked by another character standing in the way.
+    wants: HashMap<Entity, (Position, Position)>,
+}
+impl Reservations {
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+        self.wants.clear();
+    }
+    /// Claims the tile, false if someone else got to it first.
+    pub fn reserve(&mut self, entity: Entity, tile: Position) -> bool {
+        *self.tiles.entry(tile).or_insert(entity) == entity
+    }
+    /// Records a step blocked by whoever stands on the tile.
+    pub fn want(&mut self, entity: Entity, from: Position, to: Position) {
+        self.wants.insert(entity, (from, to));
+    }
+}
+
+/// Swaps characters walking into each other by lifting both off the map,
+/// each then steps onto the other's tile when characters move.
+/// Anyone blocked by someone not coming their way waits for them to move.
+pub fn swap_update(
+    mut map: ResMut<Map>,
+    reservations: Res<Reservations>,
+    mut query: Query<(&mut BehaviorData<PathfinderBehavior>, &mut MovementInput)>,
+) {
+    let mut swapped = HashSet::<Entity>::new();
+    for (entity, (from, to)) in reservations.wants.iter() {
+        if swapped.contains(entity) { continue; }
+        let other = if let Some(Tile::Ground { occupier: Some(Occupier { entity, character_type: Some(_), .. }), .. }) = to.get_from_map(&map) {
+            *entity
+        } else {
+            continue;
+        };
+        if swapped.contains(&other) || query.get(*entity).is_err() || query.get(other).is_err() { continue; }
+        let head_on = reservations.wants.get(&other).map_or(false, |(other_from, other_to)| other_from == to && other_to == from);
+        if !head_on { continue; }
+        for (mover, from, to) in [(*entity, from, to), (other, to, from)] {
+            if let Some(Tile::Ground { occupier, .. }) = from.get_mut_from_map(&mut map) {
+                *occupier = None;
+            }
+            if let Ok((mut pathfinder, mut movement_input)) = query.get_mut(mover) {
+                pathfinder.behavior.pathfinder.path_index += 1;
+                pathfinder.behavior.pathfinder.stuck_ticks = 0;
+                *movement_input = MovementInput::from_offset(*to - *from);
+            }
+        }
+        swapped.insert(*entity);
+        swapped.insert(other);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use super::*;
+    use crate::{
+        character::{npc_movement_update, ActionHistory},
+        noise::Noise,
+        schedule::Routine,
+        testing::{self, named},
+        util::spawn_lerain,
+    };
+
+    fn app() -> App {
+        let mut app = testing::app(Map::new(10, 10));
+        app.add_system(swap_update.label("swap"))
+            .add_system(npc_movement_update.after("swap"));
+        testing::spawn(&mut app.world, |commands, map| {
+            spawn_lerain(commands, map, "A", Position::new(4, 5), Routine::default(), Vec::new());
+            spawn_lerain(commands, map, "B", Position::new(5, 5), Routine::default(), Vec::new());
+        });
+        app
+    }
+
+    fn occupier(world: &World, position: Position) -> Option<Entity> {
+        match position.get_from_map(world.resource::<Map>()) {
+            Some(Tile::Ground { occupier, .. }) => occupier.as_ref().map(|occupier| occupier.entity),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn walking_into_each_other_swaps_places() {
+        let mut app = app();
+        let (a, b) = (named(&mut app.world, "A"), named(&mut app.world, "B"));
+        let mut reservations = app.world.resource_mut::<Reservations>();
+        reservations.want(a, Position::new(4, 5), Position::new(5, 5));
+        reservations.want(b, Position::new(5, 5), Position::new(4, 5));
+        app.update();
+
+        assert_eq!(*app.world.get::<Position>(a).unwrap(), Position::new(5, 5));
+        assert_eq!(*app.world.get::<Position>(b).unwrap(), Position::new(4, 5));
+        assert_eq!(occupier(&app.world, Position::new(5, 5)), Some(a));
+        assert_eq!(occupier(&app.world, Position::new(4, 5)), Some(b));
+        // Stepped like any other move.
+        assert!(matches!(app.world.get::<ActionHistory>(a).unwrap().get_latest(), Some(MovementInput::East)));
+        assert!(matches!(app.world.get::<ActionHistory>(b).unwrap().get_latest(), Some(MovementInput::West)));
+        assert_eq!(app.world.resource::<Noise>().events().len(), 2);
+    }
+
+    #[test]
+    fn standing_in_the_way_is_not_a_swap() {
+        let mut app = app();
+        let (a, b) = (named(&mut app.world, "A"), named(&mut app.world, "B"));
+        app.world.resource_mut::<Reservations>().want(a, Position::new(4, 5), Position::new(5, 5));
+        app.update();
+
+        assert_eq!(*app.world.get::<Position>(a).unwrap(), Position::new(4, 5));
+        assert_eq!(*app.world.get::<Position>(b).unwrap(), Position::new(5, 5));
+        assert_eq!(occupier(&app.world, Position::new(4, 5)), Some(a));
+        assert_eq!(occupier(&app.world, Position::new(5, 5)), Some(b));
+    }
+}
@@ -0,0 +1,147 @@
+use std::{collections::{HashMap, VecDeque}, mem::{discriminant, Discriminant}};
+use pathfinding::prelude::astar;
+use crate::physics::{Map, Position, Tile, Zone};
+
+/// Width and height of the cells open areas are cut into,
+/// so large rooms still make for a useful graph.
+const CLUSTER_SIZE: i32 = 16;
+
+/// Doorway from one room into a neighboring room.
+pub struct Portal {
+    pub room: usize,
+    /// Tile on our side of the doorway.
+    pub from: Position,
+    /// Tile on their side of the doorway.
+    pub to: Position,
+}
+
+/// Rooms and the doorways between them, derived from the map.
+#[derive(Default)]
+pub struct RoomGraph {
+    revision: Option<u64>,
+    size_x: usize,
+    size_y: usize,
+    rooms: Vec<Option<usize>>,
+    portals: Vec<Vec<Portal>>,
+}
+impl RoomGraph {
+    /// Rebuilds the graph if the map layout changed since last time.
+    pub fn refresh(&mut self, map: &Map) {
+        if self.revision == Some(map.revision()) { return; }
+        self.build(map);
+        self.revision = Some(map.revision());
+    }
+    pub fn room_at(&self, position: &Position) -> Option<usize> {
+        let (x, y) = position.to_coordinates()?;
+        if x >= self.size_x || y >= self.size_y { return None; }
+        self.rooms.get(x + self.size_x * y).cloned().flatten()
+    }
+    /// Can one walk from one position to the other?
+    pub fn is_connected(&self, from: &Position, to: &Position) -> bool {
+        match (self.room_at(from), self.room_at(to)) {
+            (Some(a), Some(b)) => a == b || self.route(from, to).is_some(),
+            _ => false,
+        }
+    }
+    pub fn portals(&self, room: usize) -> &Vec<Portal> {
+        &self.portals[room]
+    }
+    /// Doorway tiles to walk through on the way from one position to another,
+    /// none if there is no route or both are in the same room.
+    pub fn route(&self, from: &Position, to: &Position) -> Option<Vec<Position>> {
+        let start_room = self.room_at(from)?;
+        let goal_room = self.room_at(to)?;
+        if start_room == goal_room { return None; }
+        let (nodes, _) = astar(
+            &(start_room, *from),
+            |(room, entry)| self.portals[*room].iter()
+                .map(|portal| ((portal.room, portal.to), manhattan(entry, &portal.from) + 1))
+                .collect::<Vec<_>>(),
+            |(_, entry)| manhattan(entry, to),
+            |(room, _)| *room == goal_room,
+        )?;
+        // Each doorway is crossed from its near side to its far side.
+        let mut waypoints = Vec::with_capacity(nodes.len() * 2);
+        for pair in nodes.windows(2) {
+            let (room, _) = pair[0];
+            let (next_room, entry) = pair[1];
+            if let Some(portal) = self.portals[room].iter().find(|p| p.room == next_room && p.to == entry) {
+                waypoints.push(portal.from);
+                waypoints.push(portal.to);
+            }
+        }
+        Some(waypoints)
+    }
+    fn build(&mut self, map: &Map) {
+        let (size_x, size_y) = (map.get_size_x(), map.get_size_y());
+        self.size_x = size_x;
+        self.size_y = size_y;
+        self.rooms = vec![None; size_x * size_y];
+        self.portals.clear();
+
+        // Flood fill rooms out of walkable ground of the same zone and cluster.
+        let key = |x: usize, y: usize| -> Option<(Discriminant<Zone>, i32, i32)> {
+            if let Some(Tile::Ground { zone, .. }) = map.get(x, y) {
+                Some((discriminant(map.zone(*zone)), x as i32 / CLUSTER_SIZE, y as i32 / CLUSTER_SIZE))
+            } else {
+                None
+            }
+        };
+        let mut room_count = 0;
+        let mut open = VecDeque::new();
+        for y in 0..size_y {
+            for x in 0..size_x {
+                if self.rooms[x + size_x * y].is_some() { continue; }
+                let room_key = if let Some(k) = key(x, y) { k } else { continue; };
+                self.rooms[x + size_x * y] = Some(room_count);
+                open.push_back((x, y));
+                while let Some((x, y)) = open.pop_front() {
+                    for (nx, ny) in neighbors(x, y, size_x, size_y) {
+                        if self.rooms[nx + size_x * ny].is_none() && key(nx, ny) == Some(room_key) {
+                            self.rooms[nx + size_x * ny] = Some(room_count);
+                            open.push_back((nx, ny));
+                        }
+                    }
+                }
+                room_count += 1;
+            }
+        }
+
+        // Every tile pair across a room border is a doorway,
+        // keep the middle one for each pair of rooms.
+        let mut doorways = HashMap::<(usize, usize), Vec<(Position, Position)>>::new();
+        for y in 0..size_y {
+            for x in 0..size_x {
+                let room = if let Some(room) = self.rooms[x + size_x * y] { room } else { continue; };
+                for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+                    if nx >= size_x || ny >= size_y { continue; }
+                    if let Some(other) = self.rooms[nx + size_x * ny] {
+                        if other != room {
+                            let (a, b) = (Position::new(x as i32, y as i32), Position::new(nx as i32, ny as i32));
+                            doorways.entry((room, other)).or_default().push((a, b));
+                            doorways.entry((other, room)).or_default().push((b, a));
+                        }
+                    }
+                }
+            }
+        }
+        self.portals = (0..room_count).map(|_| Vec::new()).collect();
+        for ((room, other), pairs) in doorways {
+            let (from, to) = pairs[pairs.len() / 2];
+            self.portals[room].push(Portal { room: other, from, to });
+        }
+    }
+}
+
+fn neighbors(x: usize, y: usize, size_x: usize, size_y: usize) -> impl Iterator<Item = (usize, usize)> {
+    let mut neighbors = Vec::with_capacity(4);
+    if x + 1 < size_x { neighbors.push((x + 1, y)); }
+    if y + 1 < size_y { neighbors.push((x, y + 1)); }
+    if x > 0 { neighbors.push((x - 1, y)); }
+    if y > 0 { neighbors.push((x, y - 1)); }
+    neighbors.into_iter()
+}
+
+fn manhattan(a: &Position, b: &Position) -> u32 {
+    ((a.x - b.x).abs() + (a.y - b.y).abs()) as u32
+}
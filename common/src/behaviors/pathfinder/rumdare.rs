@@ -1,26 +1,40 @@
-use bevy::prelude::Query;
-use crate::{physics::{Map, MapCache, Position}, character::{CharacterType, CharacterData}, map_brain::CharacterBehaviorData};
-use super::{PathfinderBehavior, util::{get_random_target, get_pathfinder_target}, data::PathfinderGlobalData};
+use crate::{character::CharacterData, map_brain::{CharacterBehaviorData, RumdareState}};
+use super::{PathfinderBehavior, TargetParams, Priority};
 
 pub fn rumdare_pathfinder(
-    data: &PathfinderGlobalData,
-    mut behavior: &mut PathfinderBehavior,
-    map: &Map,
-    map_cache: &mut MapCache,
-    character_type: &CharacterType,
-    character_data: &mut CharacterData,
+    behavior: &mut PathfinderBehavior,
+    _character_data: &mut CharacterData,
     character_behavior_data: &mut CharacterBehaviorData,
-    position: &Position,
-    query: &Query<(&CharacterType, &Position)>,
+    params: TargetParams,
 ) {
-    if !get_pathfinder_target(
-        &mut behavior,
-        map,
-        map_cache,
-        &position,
-        query,
-        CharacterType::Werewolf,
-    ) {
-        get_random_target(map, &mut behavior);
+    if let CharacterBehaviorData::Rumdare { rumdare_state } = character_behavior_data {
+        match rumdare_state {
+            RumdareState::Patrol(index) => {
+                if let Some(patrol) = params.patrol {
+                    if behavior.is_at(*params.position) {
+                        // Reached a point, on to the next one.
+                        if patrol.get(*index) == Some(*params.position) {
+                            *index = patrol.next(*index);
+                        }
+                        if let Some(point) = patrol.get(*index) {
+                            behavior.set_goal(point, Priority::Low);
+                        }
+                    }
+                }
+            },
+            RumdareState::Investigate(target) => {
+                if *params.position == *target {
+                    // Nothing here, back to the route.
+                    let index = params.patrol.map_or(0, |patrol| patrol.nearest(params.position));
+                    *rumdare_state = RumdareState::Patrol(index);
+                    behavior.set_goal(*params.position, Priority::Low);
+                } else {
+                    behavior.set_goal(*target, Priority::Medium);
+                }
+            },
+            RumdareState::Engage(target) => {
+                behavior.set_goal(*target, Priority::High);
+            },
+        }
     }
 }
@@ -1,7 +1,6 @@
-use std::cmp::Ordering;
-use bevy::prelude::Query;
+use bevy::prelude::{Query, Without};
 use rand::Rng;
-use crate::{physics::{Map, MapCache, Position}, character::CharacterType};
+use crate::{physics::{Map, MapCache, Position}, character::CharacterType, level::Dormant, spatial::{SpatialIndex, Layer}};
 use super::PathfinderBehavior;
 
 pub fn get_pathfinder_target(
@@ -9,40 +8,22 @@ pub fn get_pathfinder_target(
     map: &Map,
     map_cache: &mut MapCache,
     position: &Position,
-    search_query: &Query<(&CharacterType, &Position)>,
+    vision_distance: u32,
+    search_query: &Query<(&CharacterType, &Position), Without<Dormant>>,
+    spatial_index: &SpatialIndex,
     target_character_type: CharacterType,
 ) -> bool {
-    let mut found_target = false;
-    let in_vision = map.get_in_vision(map_cache, position.clone());
-    let mut check_found_target = |pos: &Position, character_type: &CharacterType| {
-        //found_target = in_vision.contains(pos) && matches!(character_type, CharacterType::Werewolf);
-        found_target = false;
-    };
-    if let Some((_, target)) = search_query.iter().min_by(|(type_a, pos_a), (type_b, pos_b)| {
-        if **type_a == target_character_type {
-            check_found_target(pos_a, type_a);
-            if **type_b == target_character_type {
-                let diff_a = position.distance(pos_a);
-                let diff_b = position.distance(pos_b);
-                diff_a.cmp(&diff_b)
-            } else {
-                Ordering::Less
-            }
-        } else if **type_b == target_character_type {
-            check_found_target(pos_b, type_b);
-            Ordering::Greater
-        } else {
-            Ordering::Equal
-        }
-    }) {
-        if found_target {
-            pathfinder.set_goal(target.clone(), super::Priority::Low);
-            true
-        } else {
-            pathfinder.set_goal(position.clone(), super::Priority::Low);
-            false
-        }
+    let in_vision = map.get_in_vision(map_cache, position.clone(), vision_distance);
+    let target = spatial_index.in_radius(*position, vision_distance)
+        .filter(|located| located.layer == Layer::Character && in_vision.contains(&located.position))
+        .filter(|located| matches!(search_query.get(located.entity), Ok((character_type, _)) if *character_type == target_character_type))
+        .map(|located| located.position)
+        .min_by_key(|p| position.distance(p));
+    if let Some(target) = target {
+        pathfinder.set_goal(target.clone(), super::Priority::Low);
+        true
     } else {
+        pathfinder.set_goal(position.clone(), super::Priority::Low);
         false
     }
 }
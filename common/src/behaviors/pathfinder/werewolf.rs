@@ -1,33 +1,17 @@
-use bevy::prelude::Query;
-use crate::{physics::{Map, MapCache, Position}, character::{CharacterType, CharacterData, WereForm}, map_brain::{CharacterBehaviorData, WerewolfState, HumanState}, util::Cooldown};
-use super::{PathfinderBehavior, data::PathfinderGlobalData, lerain::human_pathfinder};
+use crate::{character::{CharacterType, CharacterData, WereForm}, map_brain::{CharacterBehaviorData, WerewolfState}, util::Cooldown};
+use super::{PathfinderBehavior, TargetParams, lerain::human_pathfinder};
 
 pub fn werewolf_pathfinder(
-    data: &PathfinderGlobalData,
-    mut behavior: &mut PathfinderBehavior,
-    map: &Map,
-    map_cache: &mut MapCache,
-    character_type: &CharacterType,
+    behavior: &mut PathfinderBehavior,
     character_data: &mut CharacterData,
     character_behavior_data: &mut CharacterBehaviorData,
-    position: &Position,
-    query: &Query<(&CharacterType, &Position)>,
+    params: TargetParams,
 ) {
     if let CharacterData::Werewolf { form } = character_data {
         match form {
             WereForm::Human => {
                 if let CharacterBehaviorData::Werewolf { human_state, .. } = character_behavior_data {
-                    human_pathfinder(
-                        character_type,
-                        human_state,
-                        data,
-                        behavior,
-                        map,
-                        map_cache,
-                        position,
-                        query,
-                        CharacterType::Player,
-                    );
+                    human_pathfinder(human_state, behavior, params, CharacterType::Player);
                 }
             },
             WereForm::Beast => {
@@ -36,8 +20,10 @@ pub fn werewolf_pathfinder(
                         WerewolfState::Hunt(target) => {
                             if let Some(target) = target {
                                 behavior.set_goal(target.clone(), super::Priority::Medium);
-                            } else {
-                                behavior.set_goal(position.clone(), super::Priority::Medium);
+                            } else if behavior.is_at(*params.position) {
+                                // Prowl the town until prey shows up.
+                                let target = params.data.target(CharacterType::Werewolf, *params.position).get();
+                                behavior.set_goal(target.0, super::Priority::Medium);
                             }
                         },
                         WerewolfState::Panic { target, enemies, exclude_target_index, calm_cooldown } => {
@@ -49,20 +35,11 @@ pub fn werewolf_pathfinder(
                                     None
                                 }
                             } else {
-                                let target = data.werewolf.panic((character_type.clone(), *position));
+                                let target = params.data.werewolf.panic((params.character_type.clone(), *params.position));
                                 let target = if let Some(except) = exclude_target_index {
-                                    if enemies.len() == 0 {
-                                        target.get_except(*except)
-                                    } else {
-                                        target.enemy(enemies[0]).get_except(*except)
-                                    }
-                                    
+                                    target.enemies(enemies).get_except(*except)
                                 } else {
-                                    if enemies.len() == 0 {
-                                        target.get()
-                                    } else {
-                                        target.enemy(enemies[0]).get()
-                                    }
+                                    target.enemies(enemies).get()
                                 };
                                 let position = target.0.clone();
                                 *werewolf_state = WerewolfState::Panic {
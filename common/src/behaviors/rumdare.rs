@@ -0,0 +1,150 @@
+use bevy::prelude::{Commands, Entity, Query, Res, ResMut, With, Without};
+use crate::{
+    map_brain::{BehaviorData, CharacterBehaviorData, RumdareState},
+    character::{CharacterData, CharacterType, WereForm, Sprite, MovementInput, PlayerTag},
+    physics::{Map, MapCache, Position, Tile, Velocity},
+    inventory::{Equipment, Item},
+    noise::Noise,
+    time::Clock,
+    util::{Cooldown, spawn_projectile},
+    level::{Dormant, LevelId},
+    relationship::{Relationships, HOSTILE},
+    Settings,
+};
+use super::{util::human_hear, pathfinder::danger::DangerZones};
+
+/// Ticks between shots.
+const RELOAD_TICKS: usize = 4;
+/// Ticks a sighting keeps every rumdare on alert.
+const ALERT_TICKS: u64 = 40;
+
+pub struct RumdareBehavior {
+    reload: Cooldown,
+}
+impl RumdareBehavior {
+    pub fn new() -> BehaviorData<RumdareBehavior> {
+        BehaviorData::new(RumdareBehavior { reload: Cooldown(0) })
+    }
+}
+
+/// Last beast sighting, shared between all rumdares.
+#[derive(Default)]
+pub struct RumdareAlert {
+    sighting: Option<(Position, u64)>,
+}
+impl RumdareAlert {
+    pub fn raise(&mut self, position: Position, tick: u64) {
+        self.sighting = Some((position, tick));
+    }
+    /// Where the beast was last seen, if recently.
+    pub fn active(&self, tick: u64) -> Option<Position> {
+        self.sighting
+            .filter(|(_, raised)| tick - raised < ALERT_TICKS)
+            .map(|(position, _)| position)
+    }
+}
+
+pub fn rumdare_update(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    mut map_cache: ResMut<MapCache>,
+    mut noise: ResMut<Noise>,
+    mut alert: ResMut<RumdareAlert>,
+    mut danger_zones: ResMut<DangerZones>,
+    clock: Res<Clock>,
+    settings: Res<Settings>,
+    relationships: Res<Relationships>,
+    mut query: Query<(
+        Entity,
+        &CharacterType,
+        &Position,
+        &mut CharacterBehaviorData,
+        &mut BehaviorData<RumdareBehavior>,
+        &Equipment,
+    ), Without<Dormant>>,
+    werewolf_query: Query<(&Position, &CharacterData), Without<Dormant>>,
+    player_query: Query<(Entity, &CharacterType, &Position, &LevelId), With<PlayerTag>>,
+) {
+    for (entity, character_type, position, mut character_behavior_data, mut rumdare, equipment) in query.iter_mut() {
+        if !map.is_active(position) { continue; }
+        if let CharacterBehaviorData::Rumdare { rumdare_state } = character_behavior_data.as_mut() {
+            let in_vision = map.get_in_vision(&mut map_cache, *position, clock.vision_distance());
+            let beast = werewolf_query.iter()
+                .filter(|(p, character_data)| {
+                    matches!(character_data, CharacterData::Werewolf { form: WereForm::Beast }) && in_vision.contains(p)
+                })
+                .map(|(p, _)| *p)
+                .min_by_key(|p| p.distance(position));
+            // The watch turns on a player it has had enough of.
+            let hostile = player_query.iter()
+                .find(|(player, player_type, p, level)| {
+                    **level == map.level()
+                        && in_vision.contains(p)
+                        && relationships.score((entity, character_type), (*player, player_type)) <= HOSTILE
+                })
+                .map(|(_, _, p, _)| *p);
+            if let Some(target) = beast.or(hostile) {
+                *rumdare_state = RumdareState::Engage(target);
+                if beast.is_some() {
+                    alert.raise(target, clock.tick());
+                    danger_zones.spot(target, clock.tick());
+                }
+                // Fire when the target is lined up.
+                if rumdare.behavior.reload.execute() {
+                    if let Some(Item::Gun { damage, speed, .. }) = equipment.equipped.as_deref() {
+                        if let Some(movement) = line_of_fire(&map, position, &target, settings.diagonal_movement) {
+                            if let Ok(offset) = movement.to_position() {
+                                spawn_projectile(
+                                    &mut commands,
+                                    &mut map,
+                                    &mut noise,
+                                    Sprite::Projectile,
+                                    *position + offset,
+                                    Velocity::new(movement, *speed),
+                                    *damage,
+                                    Some(entity),
+                                );
+                                rumdare.behavior.reload = Cooldown(RELOAD_TICKS);
+                            }
+                        }
+                    }
+                }
+            } else {
+                match rumdare_state {
+                    RumdareState::Engage(last_seen) => {
+                        // Lost sight of it, go to where it was.
+                        *rumdare_state = RumdareState::Investigate(*last_seen);
+                    },
+                    RumdareState::Patrol(_) => {
+                        if let Some(sighting) = alert.active(clock.tick()) {
+                            *rumdare_state = RumdareState::Investigate(sighting);
+                        } else if let Some(origin) = human_hear(&noise, position) {
+                            *rumdare_state = RumdareState::Investigate(origin);
+                        }
+                    },
+                    RumdareState::Investigate(_) => {},
+                }
+            }
+        }
+    }
+}
+
+/// Direction to shoot in if the target is in a straight, unobstructed line.
+fn line_of_fire(map: &Map, position: &Position, target: &Position, diagonal: bool) -> Option<MovementInput> {
+    let difference = target - position;
+    let is_straight = difference.x == 0 || difference.y == 0;
+    let is_diagonal = diagonal && difference.x.abs() == difference.y.abs();
+    if !is_straight && !is_diagonal { return None; }
+    let movement = MovementInput::from_offset(difference);
+    let offset = movement.to_position().ok()?;
+    let mut p = *position;
+    loop {
+        if map.cuts_corner(&p, &offset) { return None; }
+        p = p + offset;
+        if p == *target { return Some(movement); }
+        match p.get_from_map(map) {
+            Some(Tile::Ground { occupier: None, .. }) => {},
+            _ => return None,
+        }
+    }
+}
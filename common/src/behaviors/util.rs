@@ -1,18 +1,24 @@
-use bevy::prelude::Entity;
-use crate::{physics::{Map, MapCache, Position, Tile, Occupier}, character::CharacterType, map_brain::HumanState};
+use crate::{physics::{Map, MapCache, Position, Tile, Occupier}, character::CharacterType, map_brain::HumanState, noise::Noise};
 use super::pathfinder::{data::PathfinderGlobalData, Priority, PathfinderBehavior};
 
-/// Returns any werewolf in vision.
-pub fn human_panic(map: &Map, map_cache: &mut MapCache, position: Position) -> Option<(Entity, Position)> {
-    let vision = map.get_in_vision(map_cache, position);
+/// Returns every werewolf in vision.
+pub fn human_panic(map: &Map, map_cache: &mut MapCache, position: Position, distance: u32) -> Vec<Position> {
+    let vision = map.get_in_vision(map_cache, position, distance);
+    let mut werewolves = Vec::new();
     for p in vision.iter() {
-        if let Some(Tile::Ground { occupier, .. } | Tile::Obstacle { occupier }) = map.get(p.x as usize, p.y as usize) {
-            if let Some(Occupier { character_type: Some(CharacterType::Werewolf), entity,  .. }) = occupier {
-                return Some((*entity, *p));
+        if let Some(Tile::Ground { occupier, .. } | Tile::Obstacle { occupier }) = p.get_from_map(map) {
+            if let Some(Occupier { character_type: Some(CharacterType::Werewolf), .. }) = occupier {
+                werewolves.push(*p);
             }
         }
     }
-    None
+    werewolves
+}
+
+/// Returns where the loudest alarming noise came from.
+pub fn human_hear(noise: &Noise, position: &Position) -> Option<Position> {
+    noise.loudest_at(position, |event| event.kind.is_alarming())
+        .map(|(event, _)| event.origin)
 }
 
 /// Sets the state to panic with high priority!
@@ -21,9 +27,9 @@ pub fn set_human_panic(
     behavior: &mut PathfinderBehavior,
     state: &mut HumanState,
     friendly: (CharacterType, Position),
-    enemy_position: Position,
+    enemies: &[Position],
 ) {
-    let (position, index) = data.human.panic(friendly).enemy(enemy_position).get();
+    let (position, index) = data.human.panic(friendly).enemies(enemies).get();
     behavior.set_goal(position, Priority::High);
     *state = HumanState::Panic(index);
 }
@@ -1,9 +1,13 @@
-use bevy::prelude::{Query, ResMut};
+use bevy::prelude::{Entity, Query, Res, ResMut, Without};
 use crate::{
     map_brain::{BehaviorData, CharacterBehaviorData, WerewolfState},
-    character::{CharacterData, Sprite, WereForm, Health},
-    physics::{Map, Position, MapCache, Tile},
+    character::{CharacterData, Sprite, WereForm},
+    physics::{Map, Position, MapCache},
     constants::{WEREWOLF_SKIP_AT, HUMAN_SKIP_AT}, util::Cooldown,
+    noise::Noise,
+    time::{Clock, MoonPhase},
+    journal::{Journal, Clue, Revealed},
+    level::Dormant,
 };
 use super::pathfinder::PathfinderBehavior;
 
@@ -19,40 +23,32 @@ impl WerewolfBehavior {
 pub fn werewolf_update(
     mut map: ResMut<Map>,
     mut map_cache: ResMut<MapCache>,
+    noise: Res<Noise>,
+    mut journal: ResMut<Journal>,
+    clock: Res<Clock>,
     mut query: Query<(
+        Entity,
         &mut CharacterData,
         &mut CharacterBehaviorData,
         &mut Sprite,
         &Position,
         &mut BehaviorData<PathfinderBehavior>,
-    )>,
-    mut health_query: Query<&mut Health>,
+        Option<&Revealed>,
+    ), Without<Dormant>>,
 ) {
-    for (mut character_data, mut character_behavior_data, mut sprite, position, mut pathfinder) in query.iter_mut() {
+    for (entity, mut character_data, mut character_behavior_data, mut sprite, position, mut pathfinder, revealed) in query.iter_mut() {
+        if !map.is_active(position) { continue; }
         if let CharacterData::Werewolf { form } = character_data.as_mut() {
-            // Attack
-            if matches!(form, WereForm::Beast) {
-                for attack_offset in [Position::new(0, 1), Position::new(1, 0), Position::new(0, -1), Position::new(-1, 0)] {
-                    let p = *position + attack_offset;
-                    if let Some(Tile::Ground { occupier, .. }) = map.get(p.x as usize, p.y as usize) {
-                        if let Some(occupier) = occupier {
-                            if let Ok(mut health) = health_query.get_mut(occupier.entity) {
-                                health.damage(1);
-                            }
-                        }
-                    }
-                }
-            }
             if let CharacterBehaviorData::Werewolf { werewolf_state, human_state } = character_behavior_data.as_mut() {
                 // Transition Forms
-                let in_vision = map.get_in_vision(&mut map_cache, position.clone());
+                let in_vision = map.get_in_vision(&mut map_cache, position.clone(), clock.vision_distance());
                 let mut character_count = 0;
                 let mut enemies = Vec::new();
                 const BEAST_FORM_COUNT: u32 = 1;
                 let mut nearest_target: Option<Position> = None;
                 for p in in_vision {
                     if p != position {
-                        if let Some(tile) = map.get(p.x as usize, p.y as usize) {
+                        if let Some(tile) = p.get_from_map(&map) {
                             if tile.is_character() {
                                 if let Some(target) = nearest_target {
                                     if p.distance(position) < target.distance(position) {
@@ -62,7 +58,7 @@ pub fn werewolf_update(
                                     nearest_target = Some(p.clone());
                                 }
                                 character_count += 1;
-                                enemies.push(position.clone());
+                                enemies.push(p.clone());
                             }
                         }
                     }
@@ -89,7 +85,17 @@ pub fn werewolf_update(
                     true
                 };
                 if can_change_form {
-                    let new_form = if character_count == BEAST_FORM_COUNT {
+                    let new_form = if revealed.is_some() {
+                        // Found out, no point in hiding.
+                        if let Some(target) = nearest_target {
+                            *werewolf_state = WerewolfState::Hunt(Some(target));
+                        }
+                        set_form(WereForm::Beast)
+                    } else if !clock.is_beast_night() {
+                        // The beast only comes out at night, and never under a new moon.
+                        *werewolf_state = WerewolfState::Hunt(None);
+                        set_form(WereForm::Human)
+                    } else if character_count == BEAST_FORM_COUNT {
                         *werewolf_state = if let Some(target) = nearest_target {
                             WerewolfState::Hunt(Some(target))
                         } else {
@@ -104,6 +110,13 @@ pub fn werewolf_update(
                             calm_cooldown: Cooldown(0),
                         };
                         set_form(WereForm::Beast)
+                    } else if let Some(origin) = hear(&noise, entity, form, position) {
+                        // Go see what made that noise.
+                        *werewolf_state = WerewolfState::Hunt(Some(origin));
+                        None
+                    } else if clock.moon_phase() == MoonPhase::Full {
+                        // The full moon forces the change, prey or not.
+                        set_form(WereForm::Beast)
                     } else if check(werewolf_state, position) {
                         *werewolf_state = WerewolfState::Hunt(None);
                         set_form(WereForm::Human)
@@ -120,6 +133,7 @@ pub fn werewolf_update(
                                 *human_state = crate::map_brain::HumanState::Idle(None);
                             },
                             WereForm::Beast => {
+                                journal.leave(*position, Clue::TornClothes(*position));
                                 sprite.set_sprite(Sprite::Werewolf, &mut map, position);
                                 pathfinder.behavior.set_skip_turn(WEREWOLF_SKIP_AT);
                             },
@@ -131,6 +145,13 @@ pub fn werewolf_update(
     }
 }
 
+/// Only the beast goes looking for noises it did not make.
+fn hear(noise: &Noise, entity: Entity, form: &WereForm, position: &Position) -> Option<Position> {
+    if !matches!(form, WereForm::Beast) { return None; }
+    noise.loudest_at(position, |event| event.source != Some(entity) && event.origin != *position)
+        .map(|(event, _)| event.origin)
+}
+
 fn check(state: &WerewolfState, position: &Position) -> bool {
     if let WerewolfState::Hunt(Some(target)) = state {
         if position != target {
@@ -1,6 +1,6 @@
 use std::{collections::VecDeque, default};
 use bevy::prelude::*;
-use crate::{physics::*, dialogue::{Dialogue, DialogueOption}, inventory::{Equipment, Inventory}, PlayerState, loot_menu::LootMenu, map_brain::HumanState};
+use crate::{physics::*, dialogue::{Dialogue, DialogueOption, DialogueTrees, DialogueContext, Effect}, inventory::{Equipment, Inventory}, PlayerState, Settings, loot_menu::LootMenu, noise::{Noise, NoiseKind}, journal::{Journal, Clue, Witness}, time::Clock, door::{Door, Push}, level::{LevelId, Levels, Stairs, Dormant}, util::spawn_floor_item, interaction::{Interactions, InteractionParams}, quest::{Quests, QuestEvent}, relationship::{self, HOSTILE}, theft::{Confront, confrontation}};
 
 #[derive(Component)]
 pub struct PlayerTag;
@@ -22,13 +22,16 @@ pub enum Sprite {
 
     Projectile,
     Chest,
+    Door,
+    Stairs,
+    Item,
     Unknown,
 }
 impl Sprite {
     pub fn set_sprite(&mut self, sprite: Sprite, map: &mut Map, position: &Position) {
         if *self == sprite { return; }
         *self = sprite;
-        if let Some(Tile::Ground { occupier, .. }) = map.get_mut(position.x as usize, position.y as usize) {
+        if let Some(Tile::Ground { occupier, .. }) = position.get_mut_from_map(map) {
             if let Some(occupier) = occupier {
                 occupier.sprite = self.clone();
             }
@@ -62,6 +65,25 @@ impl Health {
     }
 }
 
+/// Ticks left before the character can take another step,
+/// rough terrain takes longer to cross.
+#[derive(Component, Default)]
+pub struct TerrainDelay(pub u32);
+impl TerrainDelay {
+    pub fn is_delayed(&self) -> bool {
+        self.0 > 0
+    }
+    /// Counts down, true once free to move.
+    fn wait(&mut self) -> bool {
+        if self.0 > 0 {
+            self.0 -= 1;
+            false
+        } else {
+            true
+        }
+    }
+}
+
 #[derive(Bundle)]
 pub struct CharacterBundle {
     pub input_data: MovementInput,
@@ -75,6 +97,8 @@ pub struct CharacterBundle {
     pub inventory: Inventory,
     pub equipment: Equipment,
     pub collision: Collision,
+    pub terrain_delay: TerrainDelay,
+    pub level: LevelId,
 }
 
 #[derive(Component)]
@@ -163,6 +187,10 @@ pub enum MovementInput {
     East,
     South,
     West,
+    NorthEast,
+    SouthEast,
+    SouthWest,
+    NorthWest,
 }
 pub struct ToPositionError;
 impl std::fmt::Display for ToPositionError {
@@ -171,6 +199,42 @@ impl std::fmt::Display for ToPositionError {
     }
 }
 impl MovementInput {
+    /// Every direction, orthogonal ones first.
+    const DIRECTIONS: [MovementInput; 8] = [
+        MovementInput::North,
+        MovementInput::East,
+        MovementInput::South,
+        MovementInput::West,
+        MovementInput::NorthEast,
+        MovementInput::SouthEast,
+        MovementInput::SouthWest,
+        MovementInput::NorthWest,
+    ];
+    /// Directions one can step in, diagonals only if allowed.
+    pub fn directions(diagonal: bool) -> &'static [MovementInput] {
+        if diagonal {
+            &Self::DIRECTIONS
+        } else {
+            &Self::DIRECTIONS[..4]
+        }
+    }
+    /// Direction of the offset, idle if there is none.
+    pub fn from_offset(offset: Position) -> MovementInput {
+        match (offset.x.signum(), offset.y.signum()) {
+            (0, 1) => MovementInput::North,
+            (1, 0) => MovementInput::East,
+            (0, -1) => MovementInput::South,
+            (-1, 0) => MovementInput::West,
+            (1, 1) => MovementInput::NorthEast,
+            (1, -1) => MovementInput::SouthEast,
+            (-1, -1) => MovementInput::SouthWest,
+            (-1, 1) => MovementInput::NorthWest,
+            _ => MovementInput::Idle,
+        }
+    }
+    pub fn is_diagonal(&self) -> bool {
+        matches!(self, MovementInput::NorthEast | MovementInput::SouthEast | MovementInput::SouthWest | MovementInput::NorthWest)
+    }
     fn to_str(&self) -> &'static str {
         match self {
             MovementInput::Idle => "Idle",
@@ -178,6 +242,10 @@ impl MovementInput {
             MovementInput::East => "East",
             MovementInput::South => "South",
             MovementInput::West => "West",
+            MovementInput::NorthEast => "North East",
+            MovementInput::SouthEast => "South East",
+            MovementInput::SouthWest => "South West",
+            MovementInput::NorthWest => "North West",
         }
     }
     pub fn to_position(&self) -> Result<Position, ToPositionError> {
@@ -187,6 +255,10 @@ impl MovementInput {
             MovementInput::East => Ok(Position::new(1, 0)),
             MovementInput::South => Ok(Position::new(0, -1)),
             MovementInput::West => Ok(Position::new(-1, 0)),
+            MovementInput::NorthEast => Ok(Position::new(1, 1)),
+            MovementInput::SouthEast => Ok(Position::new(1, -1)),
+            MovementInput::SouthWest => Ok(Position::new(-1, -1)),
+            MovementInput::NorthWest => Ok(Position::new(-1, 1)),
         }
     }
 }
@@ -210,6 +282,8 @@ pub enum InteractData {
     Projectile {
         recent_spawn: bool,
         damage: i32,
+        /// Whoever fired it.
+        shooter: Option<Entity>,
     },
 }
 impl Interact {
@@ -264,7 +338,10 @@ fn check_collision_and_move(
             Tile::Ground { occupier: ref mut old_value, .. } |
             Tile::Obstacle { occupier: ref mut old_value }
         ) = old_position.get_mut_from_map(map) {
-            *old_value = None;
+            // Unless someone swapping places with us got there first.
+            if old_value.as_ref().map_or(false, |occupier| occupier.entity == entity) {
+                *old_value = None;
+            }
         }
         CollisionCheckResult::NoCollision
     } else {
@@ -287,7 +364,7 @@ pub fn check_collision_and_move_or_interact(
         if let Some(
             Tile::Ground { occupier, .. } |
             Tile::Obstacle { occupier }
-        ) = map.get_mut(position.x as usize, position.y as usize) {
+        ) = position.get_mut_from_map(map) {
             if let Some(occupier) = occupier {
                 interact.info = Some(InteractInfo {
                     entity,
@@ -313,8 +390,17 @@ fn move_update(
     sprite: Option<&Sprite>,
     interact: &mut Interact,
     action_history: Option<&mut ActionHistory>,
+    mut terrain_delay: Option<&mut TerrainDelay>,
 ) {
+    if let Some(terrain_delay) = terrain_delay.as_deref_mut() {
+        if !terrain_delay.wait() { return; }
+    }
     if let Ok(movement) = input.to_position() {
+        if map.cuts_corner(position, &movement) {
+            // Squeezing past the corner of a wall is a collision.
+            collision.collided = true;
+            return;
+        }
         if check_collision_and_move_or_interact(
             map,
             entity,
@@ -328,11 +414,15 @@ fn move_update(
             if let Some(action_history) = action_history {
                 action_history.add(*input);
             }
+            if let Some(terrain_delay) = terrain_delay {
+                terrain_delay.0 = map.move_ticks(position) - 1;
+            }
         }
     }
 }
 pub fn player_movement_update(
     mut map: ResMut<Map>,
+    mut noise: ResMut<Noise>,
     mut player_query: Query<(
         Entity,
         &MovementInput,
@@ -342,32 +432,42 @@ pub fn player_movement_update(
         &mut Collision,
         &mut Interact,
         Option<&mut ActionHistory>,
+        Option<&mut TerrainDelay>,
     ), With<PlayerTag>>,
 ) {
-    for (entity, input, character_type, mut position, sprite, mut collision, mut interact, mut action_history) in player_query.iter_mut() {
+    for (entity, input, character_type, mut position, sprite, mut collision, mut interact, mut action_history, mut terrain_delay) in player_query.iter_mut() {
         let c = if let Some(character_type) = character_type {
             Some(character_type.clone())
         } else {
             None
         };
+        // Move a copy so standing still does not count as a change.
+        let mut new_position = *position;
         move_update(
             &mut map,
             entity,
             c,
             &mut collision,
             input,
-            &mut position,
+            &mut new_position,
             sprite,
             &mut interact,
             action_history.as_deref_mut(),
+            terrain_delay.as_deref_mut(),
         );
+        if new_position != *position {
+            *position = new_position;
+            noise.emit(&map, NoiseKind::Footsteps, *position, Some(entity));
+        }
     }
 }
 pub fn npc_movement_update(
     mut map: ResMut<Map>,
-    mut npc_query: Query<(Entity, &mut MovementInput, Option<&CharacterType>, &mut Position, Option<&Sprite>, &mut Collision, &mut Interact, Option<&mut ActionHistory>, Option<&Velocity>), Without<PlayerTag>>,
+    mut noise: ResMut<Noise>,
+    mut npc_query: Query<(Entity, &mut MovementInput, Option<&CharacterType>, &mut Position, Option<&Sprite>, &mut Collision, &mut Interact, Option<&mut ActionHistory>, Option<&Velocity>, Option<&mut TerrainDelay>), (Without<PlayerTag>, Without<Dormant>)>,
 ) {
-    for (entity, mut movement_input, character_type, mut position, sprite, mut collision, mut interact, mut action_history, velocity) in npc_query.iter_mut() {
+    for (entity, mut movement_input, character_type, mut position, sprite, mut collision, mut interact, mut action_history, velocity, mut terrain_delay) in npc_query.iter_mut() {
+        if !map.is_active(&position) { continue; }
         let times = if let Some(velocity) = velocity {
             if let InteractData::Projectile { ref mut recent_spawn, .. } = interact.data {
                 if *recent_spawn {
@@ -389,6 +489,8 @@ pub fn npc_movement_update(
         } else {
             None
         };
+        // Move a copy so standing still does not count as a change.
+        let mut new_position = *position;
         for _ in 0..times {
             move_update(
                 &mut map,
@@ -396,17 +498,28 @@ pub fn npc_movement_update(
                 c.clone(),
                 &mut collision,
                 &movement_input,
-                &mut position,
+                &mut new_position,
                 sprite,
                 &mut interact,
                 action_history.as_deref_mut(),
+                terrain_delay.as_deref_mut(),
             );
         }
+        if new_position != *position {
+            *position = new_position;
+            if c.is_some() {
+                noise.emit(&map, NoiseKind::Footsteps, *position, Some(entity));
+            }
+        }
     }
 }
-pub fn player_movement_input_update(player_input: Res<PlayerInput>, mut query: Query<&mut MovementInput, With<PlayerTag>>) {
+pub fn player_movement_input_update(player_input: Res<PlayerInput>, settings: Res<Settings>, mut query: Query<&mut MovementInput, With<PlayerTag>>) {
     for mut movement_input in query.iter_mut() {
-        *movement_input = player_input.input_movement;
+        *movement_input = if player_input.input_movement.is_diagonal() && !settings.diagonal_movement {
+            MovementInput::Idle
+        } else {
+            player_input.input_movement
+        };
     }
 }
 
@@ -414,39 +527,101 @@ pub fn player_movement_input_update(player_input: Res<PlayerInput>, mut query: Q
 pub struct LootableTag;
 pub fn interact_update(
     mut query: Query<&mut Interact>,
-    mut commands: Commands,
     mut player_state: ResMut<PlayerState>,
-    mut map: ResMut<Map>,
     mut dialogue: ResMut<Dialogue>,
     mut loot_menu: ResMut<LootMenu>,
+    mut levels: ResMut<Levels>,
+    interactions: Res<Interactions>,
+    dialogue_trees: Res<DialogueTrees>,
+    mut params: InteractionParams,
 
-    character_type_query: Query<&CharacterType>,
-    mut health_query: Query<&mut Health>,
-    mut lootable_query: Query<&mut Inventory, With<LootableTag>>,
+    inventory_query: Query<&Inventory, Without<LootableTag>>,
+    mut door_query: Query<&mut Door>,
+    stairs_query: Query<&Stairs>,
+    confront_query: Query<&Confront>,
 ) {
     for mut interact in query.iter_mut() {
         if let Some(info) = &interact.info {
+            if let Ok(stairs) = stairs_query.get(info.other_entity) {
+                if !matches!(interact.data, InteractData::Projectile { .. }) {
+                    levels.travel(info.entity, stairs.level, stairs.position);
+                }
+            }
             match interact.data {
                 InteractData::Player => {
-                    if let Ok(_character_type) = character_type_query.get(info.other_entity) {
-                        *player_state = dialogue.activate(*player_state, info.other_entity, "Bruh".to_string(), vec![
-                            ("Option 1".to_string(), DialogueOption::Leave),
-                            ("Option 2".to_string(), DialogueOption::Leave),
-                            ("Option 3".to_string(), DialogueOption::Leave),
-                        ]);
+                    if let Ok(mut door) = door_query.get_mut(info.other_entity) {
+                        let inventory = inventory_query.get(info.entity).ok();
+                        let text = match door.push(&mut params.map, &info.other_position, info.other_entity, inventory, true, params.clock.tick()) {
+                            Push::Opened => None,
+                            Push::Locked => Some("The door is locked."),
+                            Push::Barred => Some("Someone is holding the door shut."),
+                        };
+                        if let Some(text) = text {
+                            let options = vec![("Leave".to_string(), DialogueOption::Leave)];
+                            *player_state = dialogue.activate(*player_state, info.other_entity, text.to_string(), options);
+                        }
+                    }
+                    if let Ok((name, character_data, witness, accused)) = params.talk_query.get(info.other_entity) {
+                        let leave = || ("Leave".to_string(), DialogueOption::Leave);
+                        *player_state = if matches!(character_data, CharacterData::Werewolf { form: WereForm::Beast }) {
+                            dialogue.activate(*player_state, info.other_entity, "*Snarls*".to_string(), vec![leave()])
+                        } else if confront_query.get(info.other_entity).map_or(false, |Confront(thief)| *thief == info.entity) {
+                            let (text, options) = confrontation(name.as_str());
+                            dialogue.activate(*player_state, info.other_entity, text, options)
+                        } else if accused.is_some() || params.opinion(info.other_entity, info.entity) <= HOSTILE {
+                            dialogue.activate(*player_state, info.other_entity, format!("{name}: I have nothing to say to you."), vec![leave()])
+                        } else {
+                            let mut options = Vec::new();
+                            if let Some(testimony) = witness.and_then(Witness::testimony) {
+                                options.push(("Ask about the night".to_string(), DialogueOption::Branch { effects: vec![Effect::Testify(testimony)], next: None }));
+                            }
+                            options.push(("Accuse of being the werewolf".to_string(), DialogueOption::Accuse));
+                            options.push(leave());
+                            params.quests.notify(QuestEvent::TalkedTo(name.to_string()));
+                            let context = DialogueContext {
+                                inventory: inventory_query.get(info.entity).ok(),
+                                quests: &params.quests,
+                                opinion: params.opinion(info.other_entity, info.entity),
+                            };
+                            dialogue.start(*player_state, &dialogue_trees, info.other_entity, name.as_str(), context, options)
+                        };
                     }
-                    if let Ok(lootable_inventory) = lootable_query.get(info.other_entity) {
+                    if let Ok(lootable_inventory) = params.lootable_query.get(info.other_entity) {
                         loot_menu.inventory = Some(info.other_entity);
                         *player_state = PlayerState::Looting;
                         //*player_state = dialogue.activate(*player_state, "LOOTABLE INVENTORY".to_string(), vec![("Option 1".to_string(), DialogueOption::Leave)]);
                     }
                 },
-                InteractData::Lerain | InteractData::Rumdare | InteractData::Werewolf => {},
-                InteractData::Projectile { damage, .. } => {
+                InteractData::Lerain | InteractData::Rumdare | InteractData::Werewolf => {
+                    interactions.run(&mut params, info);
+                    // Caught up with the thief.
+                    if let (Ok(Confront(thief)), Ok((name, ..))) = (confront_query.get(info.entity), params.talk_query.get(info.entity)) {
+                        if *thief == info.other_entity {
+                            let (text, options) = confrontation(name.as_str());
+                            *player_state = dialogue.activate(*player_state, info.entity, text, options);
+                        }
+                    }
+                    if let Ok(mut door) = door_query.get_mut(info.other_entity) {
+                        if matches!(params.talk_query.get(info.entity), Ok((_, CharacterData::Werewolf { form: WereForm::Beast }, ..))) {
+                            // Break it down!
+                            if let Ok(mut health) = params.health_query.get_mut(info.other_entity) {
+                                health.damage(1);
+                            }
+                            params.noise.emit(&params.map, NoiseKind::Attack, info.other_position, Some(info.entity));
+                        } else {
+                            let inventory = inventory_query.get(info.entity).ok();
+                            door.push(&mut params.map, &info.other_position, info.other_entity, inventory, false, params.clock.tick());
+                        }
+                    }
+                },
+                InteractData::Projectile { damage, shooter, .. } => {
                     // Collision!
-                    map.destroy(info.position.x as usize, info.position.y as usize, &mut commands);
-                    if let Ok(mut health) = health_query.get_mut(info.other_entity) {
+                    params.map.destroy(&info.position, &mut params.commands);
+                    if let Ok(mut health) = params.health_query.get_mut(info.other_entity) {
                         health.damage(damage);
+                        if let Some(shooter) = shooter {
+                            params.relationships.change(info.other_entity, shooter, relationship::SHOT);
+                        }
                     }
                 },
             }
@@ -455,28 +630,106 @@ pub fn interact_update(
     }
 }
 
+/// Projectiles are spent against walls, chipping away at them,
+/// and the beast smashes whatever it runs into.
 pub fn collision_update(
-    mut query: Query<(&mut Collision, &Position)>,
+    mut query: Query<(&mut Collision, &Position, &MovementInput, &Interact, Option<&CharacterData>, Option<&Velocity>), Without<Dormant>>,
     mut commands: Commands,
     mut map: ResMut<Map>,
 ) {
-    for (mut collision, position) in query.iter_mut() {
+    for (mut collision, position, movement_input, interact, character_data, velocity) in query.iter_mut() {
         if collision.collided {
             collision.collided = false;
+            // Projectiles strike whatever lies the way they fly.
+            let heading = velocity.map_or(movement_input, |velocity| &velocity.movement);
+            let struck = heading.to_position().ok().map(|movement| *position + movement);
             match collision.collision_type {
                 CollisionType::Sensor => {
-                    map.destroy(position.x as usize, position.y as usize, &mut commands);
+                    if let (Some(struck), InteractData::Projectile { damage, .. }) = (struck, &interact.data) {
+                        map.damage_tile(&struck, *damage as u32);
+                    }
+                    map.destroy(position, &mut commands);
+                },
+                _ => if let (Some(struck), Some(CharacterData::Werewolf { form: WereForm::Beast })) = (struck, character_data) {
+                    map.damage_tile(&struck, 1);
                 },
-                _ => {},
             }
         }
     }
 }
 
-pub fn destroy_check_update(mut commands: Commands, mut map: ResMut<Map>, query: Query<(&Position, &Health)>) {
-    for (position, health) in query.iter() {
+pub fn destroy_check_update(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    mut journal: ResMut<Journal>,
+    clock: Res<Clock>,
+    mut quests: ResMut<Quests>,
+    mut query: Query<(&Position, &Health, Option<&Name>, Option<&mut Equipment>, Option<&PlayerTag>), (Without<Door>, Without<Dormant>)>,
+) {
+    for (position, health, name, equipment, player) in query.iter_mut() {
         if health.value == 0 {
-            map.destroy(position.x as usize, position.y as usize, &mut commands);
+            if let Some(name) = name {
+                journal.record(&clock, Clue::Disappearance(name.to_string()));
+                quests.notify(QuestEvent::Died(name.to_string()));
+            }
+            if player.is_some() {
+                quests.notify(QuestEvent::PlayerDied);
+            }
+            // Whatever was held falls to the ground.
+            if let Some(item) = equipment.and_then(|mut equipment| equipment.equipped.take()) {
+                spawn_floor_item(&mut commands, &map, *position, item);
+            }
+            map.destroy(position, &mut commands);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing, util::spawn_projectile};
+
+    /// A bullet flying east from (3, 5) at two tiles a tick, with an obstacle in the way.
+    fn app(damage: i32) -> App {
+        let mut map = Map::new(12, 12);
+        map.set_tile(Position::new(5, 5), Tile::Obstacle { occupier: None });
+        let mut app = testing::app(map);
+        app.add_system(npc_movement_update.label("movement"))
+            .add_system(collision_update.after("movement"));
+        testing::spawn(&mut app.world, |commands, map| {
+            let velocity = Velocity::new(MovementInput::East, 2);
+            spawn_projectile(commands, map, &mut Noise::default(), Sprite::Projectile, Position::new(3, 5), velocity, damage, None);
+        });
+        app
+    }
+
+    fn projectiles(world: &mut World) -> usize {
+        world.query::<&Interact>().iter(world)
+            .filter(|interact| matches!(interact.data, InteractData::Projectile { .. }))
+            .count()
+    }
+
+    #[test]
+    fn bullets_break_obstacles_in_their_way() {
+        let mut app = app(3);
+        // Left where it was fired the first tick, then strikes the obstacle halfway through its flight.
+        app.update();
+        app.update();
+        assert_eq!(projectiles(&mut app.world), 0);
+        let map = app.world.resource::<Map>();
+        assert!(matches!(map.get(5, 5), Some(Tile::Ground { occupier: None, .. })));
+        assert!(matches!(map.zone_at(&Position::new(5, 5)), Some(Zone::Rubble)));
+        assert!(matches!(map.get(4, 5), Some(Tile::Ground { occupier: None, .. })));
+    }
+
+    #[test]
+    fn obstacles_take_hits_before_breaking() {
+        let mut app = app(1);
+        app.update();
+        app.update();
+        assert_eq!(projectiles(&mut app.world), 0);
+        let map = app.world.resource::<Map>();
+        assert!(matches!(map.get(5, 5), Some(Tile::Obstacle { .. })));
+        assert!(map.is_breakable(&Position::new(5, 5)));
+    }
+}
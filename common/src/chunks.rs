@@ -0,0 +1,66 @@
+use bevy::prelude::{Query, ResMut, With};
+use crate::{
+    physics::{Map, Position, Tile, ZoneId},
+    character::PlayerTag,
+};
+
+/// Tiles on a side of a chunk.
+pub const CHUNK_SIZE: usize = 16;
+/// Chunks away from the player still simulated.
+const ACTIVE_CHUNK_DISTANCE: usize = 8;
+
+/// What a chunk nobody has touched reads as.
+static UNTOUCHED: Tile = Tile::Ground { occupier: None, zone: ZoneId::ROAD };
+
+/// Tiles stored in square chunks, each allocated once something in it is changed.
+/// Coordinates are expected to be within the size given.
+pub struct Chunks {
+    chunks: Vec<Option<Box<[Tile]>>>,
+    chunks_x: usize,
+}
+impl Chunks {
+    pub fn new(size_x: usize, size_y: usize) -> Self {
+        let chunks_x = (size_x + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        let chunks_y = (size_y + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        Chunks {
+            chunks: (0..chunks_x * chunks_y).map(|_| None).collect(),
+            chunks_x,
+        }
+    }
+    /// The chunk and the index of the tile within it.
+    fn locate(&self, x: usize, y: usize) -> (usize, usize) {
+        let chunk = x / CHUNK_SIZE + (y / CHUNK_SIZE) * self.chunks_x;
+        let index = x % CHUNK_SIZE + (y % CHUNK_SIZE) * CHUNK_SIZE;
+        (chunk, index)
+    }
+    pub fn get(&self, x: usize, y: usize) -> &Tile {
+        let (chunk, index) = self.locate(x, y);
+        self.chunks[chunk].as_ref().map_or(&UNTOUCHED, |tiles| &tiles[index])
+    }
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut Tile {
+        let (chunk, index) = self.locate(x, y);
+        let tiles = self.chunks[chunk].get_or_insert_with(|| {
+            (0..CHUNK_SIZE * CHUNK_SIZE).map(|_| UNTOUCHED.clone()).collect()
+        });
+        &mut tiles[index]
+    }
+    /// Chunks holding tiles of their own.
+    pub fn allocated(&self) -> usize {
+        self.chunks.iter().filter(|chunk| chunk.is_some()).count()
+    }
+}
+
+/// Chunk coordinates of the position.
+pub fn chunk_of(position: &Position) -> Option<(usize, usize)> {
+    position.to_coordinates().map(|(x, y)| (x / CHUNK_SIZE, y / CHUNK_SIZE))
+}
+
+/// Keeps the simulation to the chunks around the player.
+pub fn active_chunks_update(
+    mut map: ResMut<Map>,
+    query: Query<&Position, With<PlayerTag>>,
+) {
+    if let Ok(position) = query.get_single() {
+        map.set_active_area(position, ACTIVE_CHUNK_DISTANCE);
+    }
+}
@@ -1,11 +1,201 @@
-use bevy::prelude::Entity;
-use crate::PlayerState;
+use std::collections::{HashMap, HashSet};
+use bevy::prelude::{App, Entity, FromWorld, Mut, With, World};
+use serde::Deserialize;
+use crate::{
+    PlayerState,
+    character::PlayerTag,
+    inventory::{Inventory, Item},
+    loot_menu::LootMenu,
+    behaviors::pathfinder::Follow,
+    quest::{Quests, QuestEvent, QuestState},
+    journal::{Journal, Clue},
+    time::Clock,
+    relationship::{Relationships, GIFT, opinion_of_player},
+    theft::{return_stolen, end_confrontation},
+};
+
+/// Tree spoken from by those without one of their own.
+const DEFAULT_TREE: &str = "Default";
+
+/// Must hold for an option to be offered.
+#[derive(Deserialize, Clone)]
+pub enum Condition {
+    /// The player carries an item of the name.
+    HasItem(String),
+    Flag(String),
+    NotFlag(String),
+    /// The quest of the id was given and is still underway.
+    QuestActive(String),
+    QuestCompleted(String),
+    /// The quest of the id has not been given yet.
+    QuestNotGiven(String),
+    /// They think at least this well of the player.
+    OpinionAtLeast(i32),
+    OpinionBelow(i32),
+}
+
+/// What choosing an option does.
+#[derive(Deserialize, Clone)]
+pub enum Effect {
+    /// Hand the player an item of the name from what they carry.
+    GiveItem(String),
+    /// Take an item of the name from the player.
+    TakeItem(String),
+    SetFlag(String),
+    ClearFlag(String),
+    /// Barter for what they have to offer.
+    StartTrade,
+    /// Walk along with the player.
+    Follow,
+    /// Stop following the player.
+    Stay,
+    /// Hand the player the quest of the id.
+    GiveQuest(String),
+    /// Think better, or worse, of the player by the amount.
+    Opinion(i32),
+    /// The player hands back everything they were seen stealing.
+    ReturnStolen,
+    /// Stop going after the player over what they took.
+    EndConfrontation,
+    /// Tell the player what they saw of the beast, which goes in the journal.
+    Testify(String),
+}
+
+#[derive(Deserialize)]
+struct OptionData {
+    text: String,
+    #[serde(default)]
+    conditions: Vec<Condition>,
+    #[serde(default)]
+    effects: Vec<Effect>,
+    /// Node the conversation continues at, it ends if none.
+    #[serde(default)]
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NodeData {
+    text: String,
+    #[serde(default)]
+    options: Vec<OptionData>,
+}
+
+#[derive(Deserialize)]
+struct TreeData {
+    start: String,
+    nodes: HashMap<String, NodeData>,
+}
+
+#[derive(Deserialize)]
+struct DialogueData {
+    trees: HashMap<String, TreeData>,
+}
+
+/// Why dialogue data could not be loaded.
+#[derive(Debug)]
+pub enum DialogueError {
+    Parse(ron::error::SpannedError),
+    /// Conditions or effects name an item there is no such thing as.
+    UnknownItem(String),
+}
+impl std::fmt::Display for DialogueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DialogueError::Parse(error) => write!(f, "{error}"),
+            DialogueError::UnknownItem(name) => write!(f, "no item is named {name}"),
+        }
+    }
+}
+impl From<ron::error::SpannedError> for DialogueError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        DialogueError::Parse(error)
+    }
+}
+
+/// What each character has to say, loaded from data and keyed by name.
+pub struct DialogueTrees {
+    trees: HashMap<String, TreeData>,
+}
+impl DialogueTrees {
+    pub fn from_ron(text: &str) -> Result<Self, DialogueError> {
+        let data = ron::from_str::<DialogueData>(text)?;
+        let options = data.trees.values()
+            .flat_map(|tree| tree.nodes.values())
+            .flat_map(|node| node.options.iter());
+        for option in options {
+            let conditions = option.conditions.iter().filter_map(|condition| match condition {
+                Condition::HasItem(name) => Some(name),
+                _ => None,
+            });
+            let effects = option.effects.iter().filter_map(|effect| match effect {
+                Effect::GiveItem(name) | Effect::TakeItem(name) => Some(name),
+                _ => None,
+            });
+            if let Some(name) = conditions.chain(effects).find(|name| Item::from_name(name).is_none()) {
+                return Err(DialogueError::UnknownItem(name.clone()));
+            }
+        }
+        Ok(DialogueTrees { trees: data.trees })
+    }
+    fn tree(&self, speaker: &str) -> Option<&TreeData> {
+        self.trees.get(speaker).or_else(|| self.trees.get(DEFAULT_TREE))
+    }
+    /// Text of the node and the options whose conditions hold.
+    fn node(&self, speaker: &str, node: Option<&str>, context: &DialogueContext, flags: &HashSet<String>) -> Option<(String, Vec<(String, DialogueOption)>)> {
+        let tree = self.tree(speaker)?;
+        let node = tree.nodes.get(node.unwrap_or(&tree.start))?;
+        let options = node.options.iter()
+            .filter(|option| option.conditions.iter().all(|condition| context.holds(condition, flags)))
+            .map(|option| (option.text.clone(), DialogueOption::Branch {
+                effects: option.effects.clone(),
+                next: option.next.clone(),
+            }))
+            .collect();
+        Some((format!("{speaker}: {}", node.text), options))
+    }
+}
+impl FromWorld for DialogueTrees {
+    fn from_world(_world: &mut World) -> Self {
+        DialogueTrees::from_ron(include_str!("../data/dialogue.ron")).expect("valid dialogue data")
+    }
+}
+
+/// What conditions are checked against, besides the flags set.
+pub struct DialogueContext<'a> {
+    /// Of the player.
+    pub inventory: Option<&'a Inventory>,
+    pub quests: &'a Quests,
+    /// What the speaker thinks of the player.
+    pub opinion: i32,
+}
+impl<'a> DialogueContext<'a> {
+    fn holds(&self, condition: &Condition, flags: &HashSet<String>) -> bool {
+        match condition {
+            Condition::HasItem(name) => self.inventory.map_or(false, |inventory| inventory.items().iter().any(|item| item.get_name() == *name)),
+            Condition::Flag(flag) => flags.contains(flag),
+            Condition::NotFlag(flag) => !flags.contains(flag),
+            Condition::OpinionAtLeast(score) => self.opinion >= *score,
+            Condition::OpinionBelow(score) => self.opinion < *score,
+            Condition::QuestActive(id) => matches!(self.quests.state(id), Some(QuestState::Active(_))),
+            Condition::QuestCompleted(id) => matches!(self.quests.state(id), Some(QuestState::Completed)),
+            Condition::QuestNotGiven(id) => self.quests.state(id).is_none(),
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct Dialogue {
     pub entity: Option<Entity>,
     pub text: String,
     pub options: Vec<(String, DialogueOption)>,
+    /// Accusation made, resolved by the app.
+    pub accused: Option<Entity>,
+    /// Set by what was said, for conditions to check.
+    pub flags: HashSet<String>,
+    /// Name of whoever we are speaking with.
+    speaker: String,
+    /// Effects of the option chosen and the node to continue at, resolved by the app.
+    chosen: Option<(Entity, Vec<Effect>, Option<String>)>,
 }
 impl Dialogue {
     pub fn activate(&mut self, current_player_state: PlayerState, entity: Entity, text: String, options: Vec<(String, DialogueOption)>) -> PlayerState {
@@ -15,12 +205,28 @@ impl Dialogue {
         self.options = options;
         PlayerState::Dialogue
     }
+    /// Opens the tree of the speaker, the options of its start node come before those given.
+    pub fn start(
+        &mut self,
+        current_player_state: PlayerState,
+        trees: &DialogueTrees,
+        entity: Entity,
+        speaker: &str,
+        context: DialogueContext,
+        options: Vec<(String, DialogueOption)>,
+    ) -> PlayerState {
+        let (text, mut tree_options) = trees.node(speaker, None, &context, &self.flags)
+            .unwrap_or_else(|| (format!("{speaker}: Hello there."), Vec::new()));
+        tree_options.extend(options);
+        self.speaker = speaker.to_string();
+        self.activate(current_player_state, entity, text, tree_options)
+    }
     pub fn select(&mut self, current_player_state: PlayerState, active: usize) -> PlayerState {
         if !matches!(current_player_state, PlayerState::Dialogue) { return current_player_state; }
         if let Some(entity) = self.entity {
             self.entity = None;
             let option = self.options[active].1.clone();
-            option.execute(self, PlayerState::None, entity);
+            return option.execute(self, PlayerState::None, entity);
         }
         PlayerState::None
     }
@@ -32,14 +238,168 @@ pub enum DialogueOption {
     Leave,
     /// Get more info, continue dialogue.
     Info(String, Vec<(String, DialogueOption)>),
+    /// Accuse them of being the werewolf, discontinue dialogue.
+    Accuse,
+    /// Option from a dialogue tree.
+    Branch {
+        effects: Vec<Effect>,
+        next: Option<String>,
+    },
 }
 impl DialogueOption {
-    fn execute(&self, dialogue: &mut Dialogue, player_state: PlayerState, entity: Entity) {
+    fn execute(&self, dialogue: &mut Dialogue, player_state: PlayerState, entity: Entity) -> PlayerState {
         match self {
-            DialogueOption::Leave => {},
+            DialogueOption::Leave => player_state,
             DialogueOption::Info(info, options) => {
-                dialogue.activate(player_state, entity, info.to_owned(), options.to_owned());
+                dialogue.activate(player_state, entity, info.to_owned(), options.to_owned())
+            },
+            DialogueOption::Accuse => {
+                dialogue.accused = Some(entity);
+                player_state
+            },
+            DialogueOption::Branch { effects, next } => {
+                dialogue.chosen = Some((entity, effects.clone(), next.clone()));
+                player_state
+            },
+        }
+    }
+}
+
+/// Removes an item of the name from what the entity carries.
+fn take_item(world: &mut World, entity: Entity, name: &str) -> Option<Box<Item>> {
+    let mut inventory = world.get_mut::<Inventory>(entity)?;
+    let index = inventory.items().iter().position(|item| item.get_name() == name)?;
+    Some(inventory.remove_item(index))
+}
+
+/// Carries out the option chosen from a dialogue tree, then continues the conversation if it goes on.
+pub fn resolve(app: &mut App) {
+    let world = &mut app.world;
+    let (entity, effects, next) = match world.resource_mut::<Dialogue>().chosen.take() {
+        Some(chosen) => chosen,
+        None => return,
+    };
+    let player = match world.query_filtered::<Entity, With<PlayerTag>>().iter(world).next() {
+        Some(player) => player,
+        None => return,
+    };
+    for effect in effects {
+        match effect {
+            Effect::GiveItem(name) => {
+                let item = take_item(world, entity, &name);
+                if let (Some(item), Some(mut inventory)) = (item, world.get_mut::<Inventory>(player)) {
+                    inventory.add_item(item);
+                    world.resource_mut::<Quests>().notify(QuestEvent::Obtained(name));
+                }
+            },
+            Effect::TakeItem(name) => {
+                let item = take_item(world, player, &name);
+                if let (Some(item), Some(mut inventory)) = (item, world.get_mut::<Inventory>(entity)) {
+                    inventory.add_item(item);
+                    let to = world.resource::<Dialogue>().speaker.clone();
+                    world.resource_mut::<Quests>().notify(QuestEvent::Delivered { item: name, to });
+                    world.resource_mut::<Relationships>().change(entity, player, GIFT);
+                }
+            },
+            Effect::SetFlag(flag) => {
+                world.resource_mut::<Dialogue>().flags.insert(flag);
+            },
+            Effect::ClearFlag(flag) => {
+                world.resource_mut::<Dialogue>().flags.remove(&flag);
+            },
+            Effect::StartTrade => if world.get::<Inventory>(entity).is_some() {
+                world.resource_mut::<LootMenu>().open_trade(entity);
+                *world.resource_mut::<PlayerState>() = PlayerState::Looting;
             },
+            Effect::Follow => {
+                world.entity_mut(entity).insert(Follow(player));
+            },
+            Effect::Stay => {
+                world.entity_mut(entity).remove::<Follow>();
+            },
+            Effect::GiveQuest(id) => {
+                world.resource_mut::<Quests>().give(&id);
+            },
+            Effect::Opinion(amount) => {
+                world.resource_mut::<Relationships>().change(entity, player, amount);
+            },
+            Effect::ReturnStolen => return_stolen(world, player),
+            Effect::EndConfrontation => end_confrontation(world, entity),
+            Effect::Testify(text) => {
+                let witness = world.resource::<Dialogue>().speaker.clone();
+                world.resource_scope(|world, mut journal: Mut<Journal>| {
+                    journal.record(world.resource::<Clock>(), Clue::Testimony { witness, text: text.clone() });
+                });
+                let player_state = *world.resource::<PlayerState>();
+                let options = vec![("Leave".to_string(), DialogueOption::Leave)];
+                let player_state = world.resource_mut::<Dialogue>().activate(player_state, entity, text, options);
+                *world.resource_mut::<PlayerState>() = player_state;
+            },
+        }
+    }
+    if let Some(next) = next {
+        let opinion = opinion_of_player(world, entity);
+        let node = {
+            let dialogue = world.resource::<Dialogue>();
+            let context = DialogueContext { inventory: world.get::<Inventory>(player), quests: world.resource::<Quests>(), opinion };
+            world.resource::<DialogueTrees>().node(&dialogue.speaker, Some(&next), &context, &dialogue.flags)
+        };
+        if let Some((text, mut options)) = node {
+            if options.is_empty() {
+                options.push(("Leave".to_string(), DialogueOption::Leave));
+            }
+            let player_state = *world.resource::<PlayerState>();
+            let player_state = world.resource_mut::<Dialogue>().activate(player_state, entity, text, options);
+            *world.resource_mut::<PlayerState>() = player_state;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        physics::{Map, Position},
+        schedule::Routine,
+        testing::{self, named, player},
+        util::spawn_lerain,
+    };
+
+    #[test]
+    fn dialogue_data_loads() {
+        assert!(DialogueTrees::from_ron(include_str!("../data/dialogue.ron")).is_ok());
+    }
+
+    #[test]
+    fn unknown_items_are_rejected() {
+        let text = r#"(trees: {"Default": (start: "hello", nodes: {"hello": (text: "Hi.", options: [(text: "Take it.", effects: [GiveItem("Anvil")])])})})"#;
+        assert!(matches!(DialogueTrees::from_ron(text), Err(DialogueError::UnknownItem(name)) if name == "Anvil"));
+    }
+
+    #[test]
+    fn given_items_come_from_the_speaker() {
+        let mut app = testing::app(Map::new(10, 10));
+        testing::spawn(&mut app.world, |commands, map| {
+            spawn_lerain(commands, map, "Hilde", Position::new(4, 4), Routine::default(), vec![Item::new_apple()]);
+            testing::spawn_player(commands, map, Position::new(4, 5), Inventory::default());
+        });
+        let hilde = named(&mut app.world, "Hilde");
+        let player = player(&mut app.world);
+        let give = |app: &mut App| {
+            let mut dialogue = app.world.resource_mut::<Dialogue>();
+            dialogue.speaker = "Hilde".to_string();
+            dialogue.chosen = Some((hilde, vec![Effect::GiveItem("Apple".to_string())], None));
+            resolve(app);
+        };
+        let apples = |world: &World, entity| world.get::<Inventory>(entity).unwrap().items().iter()
+            .filter(|item| item.get_name() == "Apple")
+            .count();
+
+        give(&mut app);
+        assert_eq!(apples(&app.world, hilde), 0);
+        assert_eq!(apples(&app.world, player), 1);
+        // She has no more to give.
+        give(&mut app);
+        assert_eq!(apples(&app.world, player), 1);
+    }
+}
@@ -0,0 +1,220 @@
+use bevy::prelude::{Commands, Component, Entity, Query, Res, ResMut, With, Without};
+use crate::{
+    physics::{Map, Position, Tile, Occupier, CollisionType, Velocity, Zone},
+    character::{Sprite, Health, PlayerTag},
+    inventory::{Inventory, Item},
+    map_brain::{CharacterBehaviorData, HumanState},
+    time::Clock,
+    level::Dormant,
+    ActionInput,
+};
+
+/// Blows it takes to break a door down.
+const DOOR_HEALTH: i32 = 6;
+/// Ticks a door stays barred after those hiding behind it calm down.
+const BAR_TICKS: u64 = 20;
+/// Squared distance from the door someone hiding inside holds it shut from.
+const BAR_DISTANCE: u32 = 8 * 8;
+/// Ticks a door pushed open waits for whoever opened it to come through.
+const HOLD_TICKS: u64 = 10;
+
+#[derive(Component)]
+pub struct Door {
+    pub open: bool,
+    pub locked: bool,
+    /// Name of the key that fits the lock, none if it cannot be locked.
+    pub key: Option<String>,
+    /// Held shut from the inside until the tick.
+    barred_until: Option<u64>,
+    /// Close again once the doorway is clear.
+    close_when_clear: bool,
+    /// Someone has stood in the doorway since it was opened.
+    passed_through: bool,
+    opened_at: u64,
+}
+/// What happens when pushing against a closed door.
+pub enum Push {
+    Opened,
+    Locked,
+    Barred,
+}
+impl Door {
+    pub fn new(key: Option<String>, locked: bool) -> Self {
+        Door {
+            open: false,
+            locked,
+            key,
+            barred_until: None,
+            close_when_clear: false,
+            passed_through: false,
+            opened_at: 0,
+        }
+    }
+    pub fn is_barred(&self, tick: u64) -> bool {
+        self.barred_until.map_or(false, |until| tick < until)
+    }
+    /// Can the door be opened without a key?
+    pub fn is_passable(&self, tick: u64) -> bool {
+        !self.locked && !self.is_barred(tick)
+    }
+    fn has_key(&self, inventory: Option<&Inventory>) -> bool {
+        match (&self.key, inventory) {
+            (Some(key), Some(inventory)) => inventory.items().iter()
+                .any(|item| matches!(item.as_ref(), Item::Key { door, .. } if door == key)),
+            _ => false,
+        }
+    }
+    /// Opens the door, unlocking it with a key from the inventory.
+    /// Someone other than the player lets it swing shut behind them.
+    pub fn push(&mut self, map: &mut Map, position: &Position, entity: Entity, inventory: Option<&Inventory>, is_player: bool, tick: u64) -> Push {
+        if self.is_barred(tick) {
+            return Push::Barred;
+        }
+        if self.locked {
+            if !self.has_key(inventory) {
+                return Push::Locked;
+            }
+            self.locked = false;
+        }
+        if let Some(Tile::Ground { occupier, .. }) = position.get_mut_from_map(map) {
+            if occupier.as_ref().map_or(false, |o| o.entity == entity) {
+                *occupier = None;
+            }
+        }
+        map.sight_changed();
+        self.open = true;
+        self.close_when_clear = !is_player;
+        self.passed_through = false;
+        self.opened_at = tick;
+        Push::Opened
+    }
+    /// Closes the door unless someone stands in the doorway.
+    fn close(&mut self, map: &mut Map, position: &Position, entity: Entity) -> bool {
+        if let Some(Tile::Ground { occupier: occupier @ None, .. }) = position.get_mut_from_map(map) {
+            *occupier = Some(Occupier::new(entity, Sprite::Door, CollisionType::Solid, None));
+            map.sight_changed();
+            self.open = false;
+            self.close_when_clear = false;
+            self.passed_through = false;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub fn spawn_door(
+    commands: &mut Commands,
+    map: &mut Map,
+    position: Position,
+    key: Option<&str>,
+    locked: bool,
+) {
+    map.spawn(
+        commands,
+        Sprite::Door,
+        position,
+        Velocity::default(),
+        CollisionType::Solid,
+        |mut entity_commands| {
+            entity_commands
+                .insert(Door::new(key.map(str::to_string), locked))
+                .insert(Health::new(DOOR_HEALTH));
+        },
+    );
+}
+
+/// Breaks down battered doors, swings doors shut behind passers-by,
+/// lets those hiding bar the door and the player close doors at hand.
+pub fn door_update(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    clock: Res<Clock>,
+    action_input: Res<ActionInput>,
+    mut query: Query<(Entity, &mut Door, &Position, &Health), Without<Dormant>>,
+    player_query: Query<(&Position, &Inventory), With<PlayerTag>>,
+    hiding_query: Query<(&Position, &CharacterBehaviorData), Without<Dormant>>,
+) {
+    let player = player_query.get_single().ok();
+    for (entity, mut door, position, health) in query.iter_mut() {
+        if health.value == 0 {
+            // Broken down.
+            if door.open {
+                commands.entity(entity).despawn();
+            } else {
+                map.destroy(position, &mut commands);
+            }
+            continue;
+        }
+
+        let is_hiding_inside = hiding_query.iter().any(|(p, character_behavior_data)| {
+            matches!(
+                character_behavior_data,
+                CharacterBehaviorData::Human { human_state: HumanState::Panic(_) } |
+                CharacterBehaviorData::Werewolf { human_state: HumanState::Panic(_), .. }
+            )
+                && p.distance(position) <= BAR_DISTANCE
+                && matches!(map.zone_at(p), Some(Zone::Home { .. }))
+        });
+        if is_hiding_inside {
+            door.barred_until = Some(clock.tick() + BAR_TICKS);
+        }
+
+        if door.open {
+            // Wait for them to step through and out of the doorway,
+            // or give up on them if they do not come.
+            if position.get_from_map(&map).map_or(false, Tile::is_character) {
+                door.passed_through = true;
+            }
+            let swing_shut = door.close_when_clear
+                && (door.passed_through || clock.tick() >= door.opened_at + HOLD_TICKS);
+            if swing_shut || is_hiding_inside {
+                door.close(&mut map, position, entity);
+            } else if let (ActionInput::CloseDoors, Some((player_position, inventory))) = (&*action_input, player) {
+                if player_position.distance(position) <= 2 && door.close(&mut map, position, entity) {
+                    // Lock it behind us if we hold the key.
+                    door.locked = door.has_key(Some(inventory));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use super::*;
+    use crate::{
+        character::{npc_movement_update, collision_update, interact_update, MovementInput},
+        schedule::Routine,
+        testing::{self, named},
+        util::spawn_lerain,
+    };
+
+    #[test]
+    fn door_swings_shut_once_passed_through() {
+        let mut app = testing::app(Map::new(10, 10));
+        testing::spawn(&mut app.world, |commands, map| {
+            spawn_door(commands, map, Position::new(5, 5), None, false);
+            spawn_lerain(commands, map, "A", Position::new(4, 5), Routine::default(), Vec::new());
+        });
+        // Same order as the game, doors are seen to before anyone moves.
+        app.add_system(door_update.label("door"))
+            .add_system(npc_movement_update.label("movement").after("door"))
+            .add_system(collision_update.label("collision").after("movement"))
+            .add_system(interact_update.after("collision"));
+        let door = app.world.query_filtered::<Entity, With<Door>>().single(&app.world);
+        let npc = named(&mut app.world, "A");
+        app.world.entity_mut(npc).insert(MovementInput::East);
+        let mut tick = || {
+            app.update();
+            (*app.world.get::<Position>(npc).unwrap(), app.world.get::<Door>(door).unwrap().open)
+        };
+
+        // Bumping into the door opens it.
+        assert_eq!(tick(), (Position::new(4, 5), true));
+        assert_eq!(tick(), (Position::new(5, 5), true));
+        assert_eq!(tick(), (Position::new(6, 5), true));
+        assert_eq!(tick(), (Position::new(7, 5), false));
+    }
+}
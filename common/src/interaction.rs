@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use bevy::{
+    prelude::{Commands, Entity, Name, Query, Res, ResMut, With},
+    ecs::system::SystemParam,
+};
+use crate::{
+    physics::Map,
+    character::{CharacterData, CharacterType, WereForm, Health, InteractInfo, LootableTag},
+    inventory::{Inventory, Equipment},
+    journal::{Journal, Clue, Witness, Accused},
+    noise::{Noise, NoiseKind},
+    time::Clock,
+    quest::Quests,
+    relationship::{Relationships, GREET},
+};
+
+/// The part someone plays in an interaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Participant {
+    /// Townsfolk, and the werewolf while it passes for one.
+    Human,
+    Beast,
+    Chest,
+}
+impl Participant {
+    pub fn of(params: &InteractionParams, entity: Entity) -> Option<Participant> {
+        match params.character_query.get(entity) {
+            Ok(CharacterData::Werewolf { form: WereForm::Beast }) => Some(Participant::Beast),
+            Ok(_) => Some(Participant::Human),
+            Err(_) => params.lootable_query.contains(entity).then(|| Participant::Chest),
+        }
+    }
+}
+
+/// Everything interactions get to change.
+#[derive(SystemParam)]
+pub struct InteractionParams<'w, 's> {
+    pub commands: Commands<'w, 's>,
+    pub map: ResMut<'w, Map>,
+    pub noise: ResMut<'w, Noise>,
+    pub journal: ResMut<'w, Journal>,
+    pub clock: Res<'w, Clock>,
+    pub quests: ResMut<'w, Quests>,
+    pub relationships: ResMut<'w, Relationships>,
+    pub talk_query: Query<'w, 's, (&'static Name, &'static CharacterData, Option<&'static mut Witness>, Option<&'static Accused>)>,
+    pub character_query: Query<'w, 's, &'static CharacterData>,
+    pub character_type_query: Query<'w, 's, &'static CharacterType>,
+    pub health_query: Query<'w, 's, &'static mut Health>,
+    pub lootable_query: Query<'w, 's, &'static mut Inventory, With<LootableTag>>,
+    pub equipment_query: Query<'w, 's, &'static mut Equipment>,
+}
+
+impl<'w, 's> InteractionParams<'w, 's> {
+    /// What the first thinks of the second, neutral if either is not a character.
+    pub fn opinion(&self, of: Entity, toward: Entity) -> i32 {
+        match (self.character_type_query.get(of), self.character_type_query.get(toward)) {
+            (Ok(of_type), Ok(toward_type)) => self.relationships.score((of, of_type), (toward, toward_type)),
+            _ => 0,
+        }
+    }
+}
+
+/// What happens when the first participant bumps into the second.
+pub type Interaction = fn(&mut InteractionParams, &InteractInfo);
+
+/// Interactions between characters and the world they bump into,
+/// keyed by who bumped into whom.
+pub struct Interactions {
+    handlers: HashMap<(Participant, Participant), Interaction>,
+}
+impl Default for Interactions {
+    fn default() -> Self {
+        let mut interactions = Interactions { handlers: HashMap::new() };
+        interactions
+            .register(Participant::Human, Participant::Human, greet)
+            .register(Participant::Beast, Participant::Human, maul)
+            .register(Participant::Human, Participant::Chest, grab_weapon);
+        interactions
+    }
+}
+impl Interactions {
+    /// Replaces whatever happened between the two before.
+    pub fn register(&mut self, initiator: Participant, target: Participant, interaction: Interaction) -> &mut Self {
+        self.handlers.insert((initiator, target), interaction);
+        self
+    }
+    /// Runs the interaction between those in the info, if there is one.
+    pub fn run(&self, params: &mut InteractionParams, info: &InteractInfo) {
+        let pair = Participant::of(params, info.entity).zip(Participant::of(params, info.other_entity));
+        if let Some(interaction) = pair.and_then(|pair| self.handlers.get(&pair)) {
+            interaction(params, info);
+        }
+    }
+}
+
+/// Townsfolk passing each other trade what they saw of the beast,
+/// growing a little fonder of each other.
+fn greet(params: &mut InteractionParams, info: &InteractInfo) {
+    params.relationships.change(info.entity, info.other_entity, GREET);
+    params.relationships.change(info.other_entity, info.entity, GREET);
+    let witness = |params: &InteractionParams, entity| params.talk_query.get(entity).ok()
+        .and_then(|(.., witness, _)| witness.cloned());
+    let (first, second) = match (witness(params, info.entity), witness(params, info.other_entity)) {
+        (Some(first), Some(second)) => (first, second),
+        _ => return,
+    };
+    if let Ok((.., Some(mut witness), _)) = params.talk_query.get_mut(info.entity) {
+        second.tell(&mut witness);
+    }
+    if let Ok((.., Some(mut witness), _)) = params.talk_query.get_mut(info.other_entity) {
+        first.tell(&mut witness);
+    }
+}
+
+/// The beast lashes out at whoever it runs into, the only way it does harm.
+fn maul(params: &mut InteractionParams, info: &InteractInfo) {
+    if let Ok(mut health) = params.health_query.get_mut(info.other_entity) {
+        health.damage(1);
+        params.noise.emit(&params.map, NoiseKind::Attack, info.other_position, Some(info.entity));
+        params.journal.leave(info.other_position, Clue::BloodTrail(info.other_position));
+    }
+}
+
+/// Empty handed townsfolk help themselves to a weapon from the chest.
+fn grab_weapon(params: &mut InteractionParams, info: &InteractInfo) {
+    if let (Ok(mut equipment), Ok(mut inventory)) = (params.equipment_query.get_mut(info.entity), params.lootable_query.get_mut(info.other_entity)) {
+        if equipment.equipped.is_some() { return; }
+        if let Some(index) = inventory.items().iter().position(|item| item.is_weapon()) {
+            equipment.equipped = Some(inventory.remove_item(index));
+        }
+    }
+}
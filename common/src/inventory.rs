@@ -1,9 +1,15 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use bevy::prelude::*;
 use crate::{
-    character::{PlayerTag, Health, Sprite, ActionHistory},
+    character::{PlayerTag, Health, Sprite, ActionHistory, CharacterData, WereForm},
     physics::{Map, Position, Velocity},
     ActionInput,
-    util::spawn_projectile,
+    util::{spawn_projectile, spawn_floor_item},
+    noise::Noise,
+    spatial::{SpatialIndex, Layer},
+    level::Dormant,
+    quest::{Quests, QuestEvent},
+    relationship::{Relationships, SHOT_NEAR, SHOT_NEAR_RADIUS},
 };
 
 #[derive(Clone)]
@@ -17,30 +23,68 @@ pub enum Item {
         damage: i32,
         speed: i32,
     },
+    Key {
+        info: ItemBasicInfo,
+        /// Name of the door the key fits.
+        door: String,
+    },
+}
+
+/// Tells an item apart from others of the same name.
+/// Given when the item is made and kept wherever it goes, dropped, picked up or traded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemId(u64);
+impl ItemId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        ItemId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
 }
 
 #[derive(Clone)]
 pub struct ItemBasicInfo {
+    id: ItemId,
     name: String,
     description: String,
 }
 
 impl Item {
+    pub fn id(&self) -> ItemId {
+        match self {
+            Item::Food { info, .. } |
+            Item::Gun { info, .. } |
+            Item::Key { info, .. } => info.id,
+        }
+    }
     pub fn get_name(&self) -> String {
         match self {
             Item::Food { info, .. } |
-            Item::Gun { info, .. } => info.name.clone(),
+            Item::Gun { info, .. } |
+            Item::Key { info, .. } => info.name.clone(),
+        }
+    }
+    pub fn is_weapon(&self) -> bool {
+        matches!(self, Item::Gun { .. })
+    }
+    /// What it is worth in trade to someone with no particular need of it.
+    pub fn value(&self) -> u32 {
+        match self {
+            Item::Food { heal, .. } => 3 * *heal as u32,
+            Item::Gun { damage, speed, .. } => 10 * (damage * speed) as u32,
+            Item::Key { .. } => 10,
         }
     }
     pub fn get_description(&self) -> String {
         match self {
             Item::Food { info, .. } |
-            Item::Gun { info, .. } => info.description.clone(),
+            Item::Gun { info, .. } |
+            Item::Key { info, .. } => info.description.clone(),
         }
     }
     fn new_food(name: String, heal: i32) -> Self {
         Item::Food {
             info: ItemBasicInfo {
+                id: ItemId::next(),
                 name,
                 description: format!("Heal for {heal}."),
             },
@@ -57,6 +101,7 @@ impl Item {
     fn new_gun(name: String, description: String, damage: i32, speed: i32) -> Self {
         Item::Gun {
             info: ItemBasicInfo {
+                id: ItemId::next(),
                 name, description,
             },
             damage,
@@ -66,6 +111,25 @@ impl Item {
     pub fn new_pistol() -> Self {
         Self::new_gun("Pistol".to_string(), "Gun".to_string(), 1, 2)
     }
+    /// The item of the name, keys go by the door they open.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Apple" => Some(Self::new_apple()),
+            "Banana" => Some(Self::new_banana()),
+            "Pistol" => Some(Self::new_pistol()),
+            _ => name.strip_suffix(" key").map(Self::new_key),
+        }
+    }
+    pub fn new_key(door: &str) -> Self {
+        Item::Key {
+            info: ItemBasicInfo {
+                id: ItemId::next(),
+                name: format!("{door} key"),
+                description: format!("Opens the {door} door."),
+            },
+            door: door.to_string(),
+        }
+    }
 }
 
 impl PartialEq for &Box<Item> {
@@ -114,15 +178,32 @@ pub struct Equipment {
     pub equipped: Option<Box<Item>>,
 }
 
+/// An item lying on the ground for anyone to pick up.
+#[derive(Component)]
+pub struct FloorItem(pub Box<Item>);
+
+/// Items lying at the position.
+fn floor_items_at(spatial_index: &SpatialIndex, floor_query: &Query<&FloorItem>, position: &Position) -> Vec<(Entity, Box<Item>)> {
+    spatial_index.at_layer(position, Layer::Floor)
+        .filter_map(|entity| floor_query.get(entity).ok().map(|floor_item| (entity, floor_item.0.clone())))
+        .collect()
+}
+
 pub fn inventory_update(
     mut commands: Commands,
     mut map: ResMut<Map>,
+    mut noise: ResMut<Noise>,
     mut action_input: ResMut<ActionInput>,
-    mut query: Query<(&Position, &mut Inventory, &mut Health, &mut Equipment, &ActionHistory), With<PlayerTag>>,
+    mut quests: ResMut<Quests>,
+    mut relationships: ResMut<Relationships>,
+    spatial_index: Res<SpatialIndex>,
+    mut query: Query<(Entity, &Position, &mut Inventory, &mut Health, &mut Equipment, &ActionHistory), With<PlayerTag>>,
+    floor_query: Query<&FloorItem>,
 ) {
-    for (position, mut inventory, mut health, mut equipment, action_history) in query.iter_mut() {
+    for (player, position, mut inventory, mut health, mut equipment, action_history) in query.iter_mut() {
         match *action_input {
-            ActionInput::None => { /* Take no action! */},
+            ActionInput::None |
+            ActionInput::CloseDoors => { /* Take no action! */},
             ActionInput::SelectFromInventory(index) => {
                 if let Some(item) = inventory.items.get_mut(index) {
                     match item.as_mut() {
@@ -131,9 +212,23 @@ pub fn inventory_update(
                             inventory.items.remove(index);
                         },
                         Item::Gun { .. } => equipment.equipped = Some(inventory.items.remove(index)),
+                        Item::Key { .. } => {},
                     }
                 }
             },
+            ActionInput::DropFromInventory(index) => {
+                if index < inventory.items.len() {
+                    let item = inventory.remove_item(index);
+                    spawn_floor_item(&mut commands, &map, *position, item);
+                }
+            },
+            ActionInput::PickUp => {
+                for (entity, item) in floor_items_at(&spatial_index, &floor_query, position) {
+                    quests.notify(QuestEvent::Obtained(item.get_name()));
+                    inventory.add_item(item);
+                    commands.entity(entity).despawn();
+                }
+            },
             ActionInput::UseEquippedItem => {
                 if let Some(equipped) = &equipment.equipped {
                     match equipped.as_ref() {
@@ -144,11 +239,17 @@ pub fn inventory_update(
                                     spawn_projectile(
                                         &mut commands,
                                         &mut map,
+                                        &mut noise,
                                         Sprite::Projectile,
                                         *position + movement,
                                         Velocity::new(latest_movement_input.clone(), *speed),
                                         *damage,
+                                        Some(player),
                                     );
+                                    // Nobody likes guns going off next to them.
+                                    for located in spatial_index.in_radius(*position, SHOT_NEAR_RADIUS).filter(|located| located.layer == Layer::Character) {
+                                        relationships.change(located.entity, player, SHOT_NEAR);
+                                    }
                                 }
                             }
                         },
@@ -160,3 +261,31 @@ pub fn inventory_update(
     }
     *action_input = ActionInput::None;
 }
+
+/// Characters pick up what they step on.
+/// The player takes everything, others only arm themselves with weapons when empty handed.
+pub fn pick_up_update(
+    mut commands: Commands,
+    mut quests: ResMut<Quests>,
+    spatial_index: Res<SpatialIndex>,
+    mut player_query: Query<(&Position, &mut Inventory), (With<PlayerTag>, Changed<Position>)>,
+    mut npc_query: Query<(&Position, &mut Equipment, &CharacterData), (Without<PlayerTag>, Without<Dormant>, Changed<Position>)>,
+    floor_query: Query<&FloorItem>,
+) {
+    for (position, mut inventory) in player_query.iter_mut() {
+        for (entity, item) in floor_items_at(&spatial_index, &floor_query, position) {
+            quests.notify(QuestEvent::Obtained(item.get_name()));
+            inventory.add_item(item);
+            commands.entity(entity).despawn();
+        }
+    }
+    for (position, mut equipment, character_data) in npc_query.iter_mut() {
+        if equipment.equipped.is_some() || matches!(character_data, CharacterData::Werewolf { form: WereForm::Beast }) {
+            continue;
+        }
+        if let Some((entity, item)) = floor_items_at(&spatial_index, &floor_query, position).into_iter().find(|(_, item)| item.is_weapon()) {
+            equipment.equipped = Some(item);
+            commands.entity(entity).despawn();
+        }
+    }
+}
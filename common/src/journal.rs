@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+use bevy::prelude::{App, Component, Entity, Mut, Name, Query, Res, ResMut, With, Without};
+use crate::{
+    physics::{Map, MapCache, Position, Tile, Occupier},
+    character::{CharacterType, CharacterData, WereForm, PlayerTag},
+    time::Clock,
+    behaviors::pathfinder::danger::DangerZones,
+    level::Dormant,
+    quest::{Quests, QuestEvent},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Clue {
+    /// Shredded clothes left where the werewolf changed.
+    TornClothes(Position),
+    /// Blood where the beast attacked.
+    BloodTrail(Position),
+    /// Someone went missing.
+    Disappearance(String),
+    /// What a townsfolk told us they saw.
+    Testimony {
+        witness: String,
+        text: String,
+    },
+    /// Who we accused, and whether we were right.
+    Accusation {
+        suspect: String,
+        werewolf: bool,
+    },
+}
+impl ToString for Clue {
+    fn to_string(&self) -> String {
+        match self {
+            Clue::TornClothes(position) => format!("Found torn clothes at {}, {}.", position.x, position.y),
+            Clue::BloodTrail(position) => format!("Found a trail of blood at {}, {}.", position.x, position.y),
+            Clue::Disappearance(name) => format!("{name} has gone missing."),
+            Clue::Testimony { witness, text } => format!("{witness} said: \"{text}\""),
+            Clue::Accusation { suspect, werewolf: true } => format!("Accused {suspect}, who was the werewolf!"),
+            Clue::Accusation { suspect, werewolf: false } => format!("Wrongly accused {suspect}."),
+        }
+    }
+}
+
+pub struct JournalEntry {
+    pub day: u64,
+    pub hour: u32,
+    pub minute: u32,
+    pub clue: Clue,
+}
+impl ToString for JournalEntry {
+    fn to_string(&self) -> String {
+        format!("Day {} {:02}:{:02} - {}", self.day + 1, self.hour, self.minute, self.clue.to_string())
+    }
+}
+
+/// Clues the player has come across.
+#[derive(Default)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+    /// Evidence lying around, not yet seen by the player.
+    evidence: HashMap<Position, Clue>,
+    /// Every position evidence was ever left at.
+    marked: HashSet<Position>,
+}
+impl Journal {
+    pub fn entries(&self) -> &Vec<JournalEntry> {
+        &self.entries
+    }
+    pub fn record(&mut self, clock: &Clock, clue: Clue) {
+        if self.entries.iter().any(|entry| entry.clue == clue) { return; }
+        self.entries.push(JournalEntry {
+            day: clock.day(),
+            hour: clock.hour(),
+            minute: clock.minute(),
+            clue,
+        });
+    }
+    /// Leaves evidence behind, recorded once the player lays eyes on it.
+    pub fn leave(&mut self, position: Position, clue: Clue) {
+        if self.marked.insert(position) {
+            self.evidence.insert(position, clue);
+        }
+    }
+}
+
+/// Townsfolk remembering what they saw at night.
+#[derive(Component, Default, Clone)]
+pub struct Witness {
+    night: Option<u64>,
+    beast: Option<(u32, Position)>,
+    seen: Vec<String>,
+}
+impl Witness {
+    /// What they tell when asked, none if they saw no beast.
+    pub fn testimony(&self) -> Option<String> {
+        let (night, (hour, position)) = (self.night?, self.beast?);
+        let mut text = format!("On night {} around {hour:02}:00, I saw the beast near {}, {}.", night + 1, position.x, position.y);
+        if !self.seen.is_empty() {
+            text.push_str(&format!(" Out late that night were {}.", self.seen.join(", ")));
+        }
+        Some(text)
+    }
+    /// Passes on our sighting of the beast to someone who has none of their own that night.
+    pub fn tell(&self, listener: &mut Witness) {
+        if let (Some(night), Some(beast)) = (self.night, self.beast) {
+            if listener.night != Some(night) || listener.beast.is_none() {
+                listener.night = Some(night);
+                listener.beast = Some(beast);
+            }
+        }
+    }
+}
+
+/// Wrongly accused, and not willing to talk anymore.
+#[derive(Component)]
+pub struct Accused;
+
+/// Werewolf found out, it has no reason to hide anymore.
+#[derive(Component)]
+pub struct Revealed;
+
+pub fn journal_update(
+    map: Res<Map>,
+    mut map_cache: ResMut<MapCache>,
+    clock: Res<Clock>,
+    mut journal: ResMut<Journal>,
+    query: Query<&Position, With<PlayerTag>>,
+) {
+    if journal.evidence.is_empty() { return; }
+    for position in query.iter() {
+        let in_vision = map.get_in_vision(&mut map_cache, *position, clock.vision_distance());
+        let found: Vec<Position> = journal.evidence.keys()
+            .filter(|p| in_vision.contains(p))
+            .cloned()
+            .collect();
+        for p in found {
+            if let Some(clue) = journal.evidence.remove(&p) {
+                journal.record(&clock, clue);
+            }
+        }
+    }
+}
+
+pub fn witness_update(
+    map: Res<Map>,
+    mut map_cache: ResMut<MapCache>,
+    clock: Res<Clock>,
+    mut danger_zones: ResMut<DangerZones>,
+    mut query: Query<(Entity, &Position, &mut Witness), Without<Dormant>>,
+    seen_query: Query<(&Name, &CharacterData)>,
+) {
+    if !clock.is_night() { return; }
+    for (entity, position, mut witness) in query.iter_mut() {
+        if witness.night != Some(clock.night()) {
+            // A new night, forget the last one.
+            *witness = Witness {
+                night: Some(clock.night()),
+                ..Default::default()
+            };
+        }
+        let in_vision = map.get_in_vision(&mut map_cache, *position, clock.vision_distance());
+        for p in in_vision.iter() {
+            if let Some(Tile::Ground { occupier: Some(Occupier { entity: other, character_type: Some(_), .. }), .. }) = p.get_from_map(&map) {
+                if *other == entity { continue; }
+                if let Ok((name, character_data)) = seen_query.get(*other) {
+                    if matches!(character_data, CharacterData::Werewolf { form: WereForm::Beast }) {
+                        witness.beast = Some((clock.hour(), *p));
+                        danger_zones.spot(*p, clock.tick());
+                    } else if !witness.seen.iter().any(|seen| seen == name.as_str()) {
+                        witness.seen.push(name.to_string());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Accuses the character of being the werewolf.
+pub fn accuse(app: &mut App, entity: Entity) {
+    let world = &mut app.world;
+    let suspect = world.get::<Name>(entity).map_or_else(|| "someone".to_string(), |name| name.to_string());
+    let werewolf = matches!(world.get::<CharacterType>(entity), Some(CharacterType::Werewolf));
+    if werewolf {
+        world.entity_mut(entity).insert(Revealed);
+        world.resource_mut::<Quests>().notify(QuestEvent::Unmasked);
+    } else {
+        world.entity_mut(entity).insert(Accused);
+    }
+    world.resource_scope(|world, mut journal: Mut<Journal>| {
+        journal.record(world.resource::<Clock>(), Clue::Accusation { suspect, werewolf });
+    });
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use bevy::prelude::{
+    Commands, Component, Entity, ParallelSystemDescriptorCoercion, Stage, SystemStage, With, Without, World,
+};
+use crate::{
+    physics::{Map, MapCache, Position, Tile, Occupier, Velocity, CollisionType},
+    character::{
+        Sprite, PlayerTag, MovementInput,
+        npc_movement_update, collision_update, interact_update, destroy_check_update,
+    },
+    behaviors::{
+        pathfinder::{
+            pathfinder_update, rooms::RoomGraph, danger::DangerZones,
+            reservations::{Reservations, swap_update},
+        },
+        werewolf::werewolf_update,
+        rumdare::rumdare_update,
+    },
+    noise::{Noise, noise_update},
+    door::door_update,
+    spatial::spatial_index_update,
+    inventory::pick_up_update,
+};
+
+/// Which level an entity belongs to.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LevelId(pub u32);
+impl LevelId {
+    pub const SURFACE: LevelId = LevelId(0);
+}
+
+/// Belongs to a level other than the one being played, left alone by the systems.
+#[derive(Component)]
+pub struct Dormant;
+
+/// What goes on in a level while the player is elsewhere.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Inactive {
+    /// Everything stands still until the player comes back.
+    Paused,
+    /// Characters keep going about their business.
+    Simulated,
+}
+
+/// Takes whoever bumps into it to another level.
+#[derive(Component)]
+pub struct Stairs {
+    pub level: LevelId,
+    /// Where on the other level we come out.
+    pub position: Position,
+}
+
+/// Everything the systems keep about a level, stored while it is not the one being played.
+struct LevelState {
+    map: Map,
+    map_cache: MapCache,
+    room_graph: RoomGraph,
+    reservations: Reservations,
+    noise: Noise,
+    danger_zones: DangerZones,
+    /// Entities on the level, dormant while it is stored.
+    entities: Vec<Entity>,
+}
+impl LevelState {
+    fn new(map: Map) -> Self {
+        LevelState {
+            entities: Vec::new(),
+            map,
+            map_cache: MapCache::default(),
+            room_graph: RoomGraph::default(),
+            reservations: Reservations::default(),
+            noise: Noise::default(),
+            danger_zones: DangerZones::default(),
+        }
+    }
+    /// Trades places with the level held in the world's resources.
+    fn swap(&mut self, world: &mut World) {
+        std::mem::swap(&mut self.map, &mut *world.resource_mut::<Map>());
+        std::mem::swap(&mut self.map_cache, &mut *world.resource_mut::<MapCache>());
+        std::mem::swap(&mut self.room_graph, &mut *world.resource_mut::<RoomGraph>());
+        std::mem::swap(&mut self.reservations, &mut *world.resource_mut::<Reservations>());
+        std::mem::swap(&mut self.noise, &mut *world.resource_mut::<Noise>());
+        std::mem::swap(&mut self.danger_zones, &mut *world.resource_mut::<DangerZones>());
+    }
+}
+
+/// The levels not being played and the stairs between them.
+/// The one being played lives in the `Map` resource.
+pub struct Levels {
+    current: LevelId,
+    stored: HashMap<LevelId, LevelState>,
+    inactive: HashMap<LevelId, Inactive>,
+    travels: Vec<(Entity, LevelId, Position)>,
+    needs_refresh: bool,
+    /// Runs the characters of simulated levels.
+    simulation: Option<SystemStage>,
+}
+impl Default for Levels {
+    fn default() -> Self {
+        let simulation = SystemStage::single_threaded()
+            .with_system(spatial_index_update.label("spatial"))
+            .with_system(noise_update.label("noise").after("spatial"))
+            .with_system(door_update.label("door").after("noise"))
+            .with_system(pathfinder_update.label("pathfinder").after("door"))
+            .with_system(werewolf_update.label("werewolf").after("pathfinder"))
+            .with_system(rumdare_update.label("rumdare").after("werewolf"))
+            .with_system(swap_update.label("swap").after("rumdare"))
+            .with_system(npc_movement_update.label("movement").after("swap"))
+            .with_system(collision_update.label("collision").after("movement"))
+            .with_system(interact_update.label("interact").after("collision"))
+            .with_system(destroy_check_update.label("destroy").after("interact"))
+            .with_system(pick_up_update.after("destroy"));
+        Levels {
+            current: LevelId::SURFACE,
+            stored: HashMap::new(),
+            inactive: HashMap::new(),
+            travels: Vec::new(),
+            needs_refresh: false,
+            simulation: Some(simulation),
+        }
+    }
+}
+impl Levels {
+    pub fn current(&self) -> LevelId {
+        self.current
+    }
+    /// Stores a level to be travelled to, its entities fall dormant.
+    pub fn add(&mut self, map: Map, inactive: Inactive) {
+        self.inactive.insert(map.level(), inactive);
+        self.stored.insert(map.level(), LevelState::new(map));
+        self.needs_refresh = true;
+    }
+    /// Takes the entity to the level once everyone has moved this tick.
+    pub fn travel(&mut self, entity: Entity, level: LevelId, position: Position) {
+        self.travels.push((entity, level, position));
+    }
+    fn is_simulated(&self, level: &LevelId) -> bool {
+        matches!(self.inactive.get(level), Some(Inactive::Simulated))
+    }
+}
+
+pub fn spawn_stairs(
+    commands: &mut Commands,
+    map: &mut Map,
+    position: Position,
+    level: LevelId,
+    destination: Position,
+) {
+    map.spawn(
+        commands,
+        Sprite::Stairs,
+        position,
+        Velocity::default(),
+        CollisionType::Solid,
+        |mut entity_commands| {
+            entity_commands.insert(Stairs { level, position: destination });
+        },
+    );
+}
+
+/// Moves travellers between levels, following the player if need be,
+/// then runs the levels simulated in the background.
+pub fn level_update(world: &mut World) {
+    let travels = std::mem::take(&mut world.resource_mut::<Levels>().travels);
+    for (entity, level, position) in travels {
+        travel(world, entity, level, position);
+    }
+    if std::mem::take(&mut world.resource_mut::<Levels>().needs_refresh) {
+        sleep_elsewhere(world);
+    }
+    simulate(world);
+}
+
+fn travel(world: &mut World, entity: Entity, level: LevelId, position: Position) {
+    let current = world.resource::<Levels>().current;
+    // Only those on the level being played travel.
+    if world.get::<LevelId>(entity) != Some(&current) || level == current { return; }
+    let mut state = match world.resource_mut::<Levels>().stored.remove(&level) {
+        Some(state) => state,
+        None => return,
+    };
+    let landed = relocate(world, entity, level, position, &mut state.map);
+    let is_player = world.get::<PlayerTag>(entity).is_some();
+    if landed && !is_player {
+        state.entities.push(entity);
+        world.entity_mut(entity).insert(Dormant);
+    }
+    world.resource_mut::<Levels>().stored.insert(level, state);
+    if landed && is_player {
+        switch(world, level);
+    }
+}
+
+/// Takes the entity off the map being run and puts it down on the map of the level,
+/// false if there is no room for it there.
+fn relocate(world: &mut World, entity: Entity, level: LevelId, position: Position, destination: &mut Map) -> bool {
+    let landing = match free_tile_near(destination, position) {
+        Some(landing) => landing,
+        None => return false,
+    };
+    let old_position = match world.get::<Position>(entity) {
+        Some(position) => *position,
+        None => return false,
+    };
+    let occupier = match old_position.get_mut_from_map(&mut world.resource_mut::<Map>()) {
+        Some(Tile::Ground { occupier, .. }) => occupier.take(),
+        _ => None,
+    };
+    let occupier = match occupier {
+        Some(occupier) => occupier,
+        None => return false,
+    };
+    place(destination, landing, occupier);
+    let mut entity_mut = world.entity_mut(entity);
+    entity_mut.insert(level).insert(landing);
+    if let Some(mut movement_input) = entity_mut.get_mut::<MovementInput>() {
+        *movement_input = MovementInput::Idle;
+    }
+    true
+}
+
+/// Makes the level the one being played, storing the current one.
+fn switch(world: &mut World, level: LevelId) {
+    let mut state = match world.resource_mut::<Levels>().stored.remove(&level) {
+        Some(state) => state,
+        None => return,
+    };
+    state.swap(world);
+    let entities = std::mem::take(&mut state.entities);
+    let mut levels = world.resource_mut::<Levels>();
+    let previous = levels.current;
+    levels.current = level;
+    levels.stored.insert(previous, state);
+    sleep_elsewhere(world);
+    set_dormant(world, &entities, false);
+}
+
+fn simulate(world: &mut World) {
+    let mut levels = world.resource_mut::<Levels>();
+    let simulated: Vec<LevelId> = levels.stored.keys()
+        .filter(|level| levels.is_simulated(level))
+        .copied()
+        .collect();
+    if simulated.is_empty() { return; }
+    let current = levels.current;
+    let mut stage = match levels.simulation.take() {
+        Some(stage) => stage,
+        None => return,
+    };
+    // Those on the level being played sit out while the others take their turn.
+    let playing: Vec<Entity> = world.query_filtered::<Entity, (With<LevelId>, Without<Dormant>)>().iter(world).collect();
+    set_dormant(world, &playing, true);
+    for level in simulated {
+        let mut state = match world.resource_mut::<Levels>().stored.remove(&level) {
+            Some(state) => state,
+            None => continue,
+        };
+        state.swap(world);
+        let entities = std::mem::take(&mut state.entities);
+        set_dormant(world, &entities, false);
+        stage.run(world);
+        // The level being played is held by the state while this one runs.
+        let travels = std::mem::take(&mut world.resource_mut::<Levels>().travels);
+        for (entity, destination, position) in travels {
+            if world.get::<LevelId>(entity) != Some(&level) || destination == level { continue; }
+            if destination == current {
+                relocate(world, entity, destination, position, &mut state.map);
+            } else if let Some(mut other) = world.resource_mut::<Levels>().stored.remove(&destination) {
+                relocate(world, entity, destination, position, &mut other.map);
+                world.resource_mut::<Levels>().stored.insert(destination, other);
+            }
+        }
+        state.swap(world);
+        world.resource_mut::<Levels>().stored.insert(level, state);
+        // Whoever spawned on the level sleeps along with it, whoever came up the stairs stays up.
+        sleep_elsewhere(world);
+    }
+    set_dormant(world, &playing, false);
+    world.resource_mut::<Levels>().simulation = Some(stage);
+}
+
+/// Puts those awake on levels other than the one being played to sleep,
+/// keeping track of them with the rest of their level.
+fn sleep_elsewhere(world: &mut World) {
+    let current = world.resource::<Levels>().current;
+    let elsewhere: Vec<(Entity, LevelId)> = world.query_filtered::<(Entity, &LevelId), Without<Dormant>>()
+        .iter(world)
+        .filter(|(_, level)| **level != current)
+        .map(|(entity, level)| (entity, *level))
+        .collect();
+    for (entity, level) in elsewhere {
+        world.entity_mut(entity).insert(Dormant);
+        if let Some(state) = world.resource_mut::<Levels>().stored.get_mut(&level) {
+            state.entities.push(entity);
+        }
+    }
+}
+
+/// Wakes or puts to sleep those of the entities still around.
+fn set_dormant(world: &mut World, entities: &[Entity], dormant: bool) {
+    for entity in entities {
+        if let Some(mut entity_mut) = world.get_entity_mut(*entity) {
+            if dormant {
+                entity_mut.insert(Dormant);
+            } else {
+                entity_mut.remove::<Dormant>();
+            }
+        }
+    }
+}
+
+/// The free ground at or around the position.
+fn free_tile_near(map: &Map, position: Position) -> Option<Position> {
+    std::iter::once(position)
+        .chain(MovementInput::directions(true).iter().filter_map(|d| d.to_position().ok()).map(|offset| position + offset))
+        .find(|p| matches!(p.get_from_map(map), Some(Tile::Ground { occupier: None, .. })))
+}
+
+fn place(map: &mut Map, position: Position, occupier: Occupier) {
+    if let Some(Tile::Ground { occupier: o, .. }) = position.get_mut_from_map(map) {
+        *o = Some(occupier);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use super::*;
+    use crate::{
+        character::Interact,
+        schedule::Routine,
+        testing::{self, named},
+        util::spawn_lerain,
+    };
+
+    const BELOW: LevelId = LevelId(1);
+
+    #[test]
+    fn simulated_character_takes_the_stairs_up() {
+        let mut app = testing::app(Map::new(10, 10));
+        let mut below = Map::new(10, 10);
+        below.set_level(BELOW);
+        testing::spawn(&mut app.world, |commands, surface| {
+            spawn_stairs(commands, &mut below, Position::new(5, 5), LevelId::SURFACE, Position::new(2, 2));
+            spawn_lerain(commands, &mut below, "A", Position::new(4, 5), Routine::default(), Vec::new());
+            spawn_lerain(commands, surface, "B", Position::new(7, 7), Routine::default(), Vec::new());
+        });
+        // Only what it takes to walk into the stairs.
+        let simulation = SystemStage::single_threaded()
+            .with_system(npc_movement_update.label("movement"))
+            .with_system(interact_update.after("movement"));
+        let mut levels = Levels { simulation: Some(simulation), ..Levels::default() };
+        levels.add(below, Inactive::Simulated);
+        app.insert_resource(levels)
+            .add_system(level_update.exclusive_system());
+
+        let a = named(&mut app.world, "A");
+        let b = named(&mut app.world, "B");
+        let stairs = app.world.query_filtered::<Entity, With<Stairs>>().single(&app.world);
+        app.world.entity_mut(a).insert(MovementInput::East);
+
+        // Bumps into the stairs and comes out on the surface.
+        app.update();
+        let world = &mut app.world;
+        assert_eq!(world.get::<LevelId>(a), Some(&LevelId::SURFACE));
+        assert_eq!(world.get::<Position>(a), Some(&Position::new(2, 2)));
+        assert!(world.get::<Dormant>(a).is_none());
+        assert!(world.get::<Dormant>(b).is_none());
+        assert!(world.get::<Dormant>(stairs).is_some());
+        assert!(matches!(
+            Position::new(2, 2).get_from_map(world.resource::<Map>()),
+            Some(Tile::Ground { occupier: Some(Occupier { entity, .. }), .. }) if *entity == a
+        ));
+        let levels = world.resource::<Levels>();
+        let below = &levels.stored[&BELOW];
+        assert!(matches!(Position::new(4, 5).get_from_map(&below.map), Some(Tile::Ground { occupier: None, .. })));
+        assert_eq!(below.entities, vec![stairs]);
+
+        // Nobody on the surface is moved by the level below any more.
+        world.entity_mut(b).insert(MovementInput::East);
+        world.get_mut::<Interact>(a).unwrap().info = None;
+        app.update();
+        assert_eq!(app.world.get::<Position>(b), Some(&Position::new(7, 7)));
+    }
+}
@@ -10,6 +10,21 @@ pub mod inventory;
 pub mod util;
 pub mod behaviors;
 pub mod loot_menu;
+pub mod noise;
+pub mod time;
+pub mod schedule;
+pub mod journal;
+pub mod door;
+pub mod level;
+pub mod chunks;
+pub mod spatial;
+pub mod interaction;
+pub mod trade;
+pub mod quest;
+pub mod relationship;
+pub mod theft;
+#[cfg(test)]
+mod testing;
 
 pub enum ActionInput {
     // Take no action.
@@ -17,15 +32,39 @@ pub enum ActionInput {
     /// Select item from inventory.
     SelectFromInventory(usize),
     UseEquippedItem,
+    /// Close doors next to the player.
+    CloseDoors,
+    /// Put the item from the inventory on the ground.
+    DropFromInventory(usize),
+    /// Pick up what lies under the player.
+    PickUp,
 }
 
 #[derive(SystemLabel, Debug, Clone, Hash, Eq, PartialEq)]
 pub enum Scene {
     Map,
     Inventory,
+    Journal,
+    Quests,
     Settings,
 }
 
+/// Options changed from the settings menu.
+pub struct Settings {
+    /// Allow moving, attacking and shooting diagonally.
+    pub diagonal_movement: bool,
+    /// Move with h, j, k, l, y, u, b and n.
+    pub vi_keys: bool,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            diagonal_movement: false,
+            vi_keys: false,
+        }
+    }
+}
+
 #[derive(Default, Clone, Copy)]
 pub enum PlayerState {
     #[default]
@@ -1,22 +1,80 @@
 use bevy::prelude::{App, Entity};
-use crate::inventory::Inventory;
+use crate::{
+    inventory::{Inventory, Item},
+    trade::Trade,
+    character::PlayerTag,
+    quest::{Quests, QuestEvent},
+    theft::check_theft,
+};
 
 #[derive(Default)]
 pub struct LootMenu {
     pub inventory: Option<Entity>,
+    /// Bartering for what is in the inventory rather than taking it.
+    pub trade: Option<Trade>,
 }
 
 impl LootMenu {
+    pub fn open_trade(&mut self, entity: Entity) {
+        self.inventory = Some(entity);
+        self.trade = Some(Trade::default());
+    }
     pub fn close(&mut self) {
         self.inventory = None;
+        self.trade = None;
+    }
+}
+
+/// Lets quests know of items that came into the hands of the player.
+fn obtained<'a>(app: &mut App, entity: Entity, items: impl Iterator<Item = &'a Box<Item>>) {
+    if app.world.get::<PlayerTag>(entity).is_none() { return; }
+    let names = items.map(|item| item.get_name()).collect::<Vec<_>>();
+    let mut quests = app.world.resource_mut::<Quests>();
+    for name in names {
+        quests.notify(QuestEvent::Obtained(name));
     }
 }
 
 pub fn transfer_item(app: &mut App, from_inventory: (Entity, usize), to_inventory: Entity) {
     if let Some(mut inventory) = app.world.entity_mut(from_inventory.0).get_mut::<Inventory>() {
         let item = inventory.remove_item(from_inventory.1);
+        let id = item.id();
+        obtained(app, to_inventory, std::iter::once(&item));
         if let Some(mut inventory) = app.world.entity_mut(to_inventory).get_mut::<Inventory>() {
             inventory.add_item(item);
         }
+        if app.world.get::<PlayerTag>(to_inventory).is_some() {
+            check_theft(&mut app.world, from_inventory.0, to_inventory, id);
+        }
     }
 }
+
+/// Swaps the items at the indices of each inventory with the other,
+/// nothing changes hands unless every item is there.
+pub fn exchange_items(app: &mut App, first: (Entity, &[usize]), second: (Entity, &[usize])) -> bool {
+    let holds = |entity: Entity, indices: &[usize]| app.world.get::<Inventory>(entity)
+        .map_or(false, |inventory| indices.iter().all(|index| *index < inventory.items().len()));
+    if first.0 == second.0 || !holds(first.0, first.1) || !holds(second.0, second.1) {
+        return false;
+    }
+    let mut take = |entity: Entity, indices: &[usize]| {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        indices.dedup();
+        let mut inventory = app.world.get_mut::<Inventory>(entity).expect("inventory checked");
+        indices.into_iter().map(|index| inventory.remove_item(index)).collect::<Vec<_>>()
+    };
+    let from_first = take(first.0, first.1);
+    let from_second = take(second.0, second.1);
+    obtained(app, second.0, from_first.iter());
+    obtained(app, first.0, from_second.iter());
+    let mut give = |entity: Entity, items: Vec<_>| {
+        let mut inventory = app.world.get_mut::<Inventory>(entity).expect("inventory checked");
+        for item in items.into_iter().rev() {
+            inventory.add_item(item);
+        }
+    };
+    give(second.0, from_first);
+    give(first.0, from_second);
+    true
+}
@@ -37,6 +37,9 @@ pub enum CharacterBehaviorData {
         werewolf_state: WerewolfState,
         human_state: HumanState,
     },
+    Rumdare {
+        rumdare_state: RumdareState,
+    },
 }
 impl CharacterBehaviorData {
     pub const fn default_human() -> Self {
@@ -45,6 +48,9 @@ impl CharacterBehaviorData {
     pub const fn default_werewolf() -> Self {
         CharacterBehaviorData::Werewolf { werewolf_state: WerewolfState::Hunt(None), human_state: HumanState::Idle(None) }
     }
+    pub const fn default_rumdare() -> Self {
+        CharacterBehaviorData::Rumdare { rumdare_state: RumdareState::Patrol(0) }
+    }
 }
 #[derive(Debug)]
 pub enum HumanState {
@@ -53,6 +59,8 @@ pub enum HumanState {
     Moving(usize),
     /// Saw beast and is unarmed or outnumbered!
     Panic(usize),
+    /// Following the routine entry at index.
+    Routine(usize),
 }
 #[derive(Debug)]
 pub enum NewObjective {
@@ -72,3 +80,13 @@ pub enum WerewolfState {
         calm_cooldown: Cooldown,
     },
 }
+
+#[derive(Debug)]
+pub enum RumdareState {
+    /// Walking the patrol route towards the point at index.
+    Patrol(usize),
+    /// Checking out a disturbance at the position.
+    Investigate(Position),
+    /// Beast in sight, last seen at the position.
+    Engage(Position),
+}
@@ -1,63 +1,55 @@
 use bevy::prelude::Commands;
 
-use crate::{physics::{Map, Tile, Position, Zone, KrillTheaterZone}, behaviors::pathfinder::data::PathfinderGlobalData, util::{spawn_chest, spawn_werewolf, spawn_lerain}, inventory::{Inventory, Item}, character::Health};
+use crate::{physics::{Map, Tile, Position, Zone, KrillTheaterZone}, behaviors::pathfinder::data::PathfinderGlobalData, util::{spawn_chest, spawn_werewolf, spawn_lerain, spawn_rumdare}, inventory::{Inventory, Item}, character::Health, schedule::Schedules, door::spawn_door, level::{LevelId, Levels, Inactive, spawn_stairs}};
 
 impl Map {
-    fn create_room<F: Fn(&mut Tile)>(&mut self, bottom_left: Position, top_right: Position, border_tile: Tile, place_tile: F) {
+    fn create_room(&mut self, bottom_left: Position, top_right: Position, border_tile: Tile, place_tile: Tile) {
         for y in bottom_left.y..=top_right.y {
             for x in bottom_left.x..=top_right.x {
-                if let Some(tile) = self.get_mut(x as usize, y as usize) {
-                    place_tile(tile);
-                }
+                self.set_tile(Position::new(x, y), place_tile.clone());
             }
         }
         // Borders
         for y in bottom_left.y..=top_right.y {
-            if let Some(tile) = self.get_mut(bottom_left.x as usize, y as usize) {
-                *tile = border_tile.clone();
-            }
-            if let Some(tile) = self.get_mut(top_right.x as usize, y as usize) {
-                *tile = border_tile.clone();
-            }
+            self.set_tile(Position::new(bottom_left.x, y), border_tile.clone());
+            self.set_tile(Position::new(top_right.x, y), border_tile.clone());
         }
         for x in bottom_left.x..=top_right.x {
-            if let Some(tile) = self.get_mut(x as usize, bottom_left.y as usize) {
-                *tile = border_tile.clone();
-            }
-            if let Some(tile) = self.get_mut(x as usize, top_right.y as usize) {
-                *tile = border_tile.clone();
-            }
+            self.set_tile(Position::new(x, bottom_left.y), border_tile.clone());
+            self.set_tile(Position::new(x, top_right.y), border_tile.clone());
         }
     }
 }
 
 impl Map {
-    fn set_krill_theater_lineup(&mut self, x: usize, y: usize, position: Position, data: &PathfinderGlobalData) {
-        if let Some(tile) = self.get_mut(x, y) {
-            let p = Position::new(x as i32, y as i32);
+    fn set_krill_theater_lineup(&mut self, p: Position, position: Position, data: &PathfinderGlobalData) {
+        if let Some(Tile::Ground { occupier, .. }) = p.get_from_map(self) {
             let z = if data.is_krill_exit(&p) {
                 KrillTheaterZone::Exit
             } else {
                 KrillTheaterZone::LineUp(position)
             };
-            match tile {
-                Tile::Ground { zone, .. } => *zone = Zone::KrillTheater {
-                    zone: z,
-                },
-                _ => {},
-            }
+            let occupier = occupier.clone();
+            let zone = self.zone_id(Zone::KrillTheater { zone: z });
+            self.set_tile(p, Tile::Ground { occupier, zone });
         }
     }
 }
-pub fn town(commands: &mut Commands, map: &mut Map, data: &PathfinderGlobalData) {
-    map.initialize::<220, 100>();
+/// Hits a wooden wall takes before it breaks.
+const WOODEN_WALL_DURABILITY: u32 = 5;
+/// Level under the storehouse.
+pub const CELLAR: LevelId = LevelId(1);
+
+pub fn town(commands: &mut Commands, map: &mut Map, data: &PathfinderGlobalData, schedules: &Schedules) {
+    map.initialize(220, 100);
 
     fn home(map: &mut Map, bottom_left: Position, top_right: Position, zone: Zone) {
+        let ground = map.ground(zone);
         map.create_room(
             bottom_left,
             top_right,
             Tile::Wall,
-            |tile| *tile = Tile::Ground { occupier: None, zone: zone.clone() },
+            ground,
         );
     }
     fn obstacle(map: &mut Map, bottom_left: Position, top_right: Position) {
@@ -65,59 +57,77 @@ pub fn town(commands: &mut Commands, map: &mut Map, data: &PathfinderGlobalData)
             bottom_left,
             top_right,
             Tile::Obstacle { occupier: None },
-            |tile| *tile = Tile::Obstacle { occupier: None },
+            Tile::Obstacle { occupier: None },
         );
     }
-    fn home_entrance(map: &mut Map, position: Position) {
-        if let Some(tile) = map.get_mut(position.x as usize, position.y as usize) {
-            *tile = Tile::Ground { occupier: None, zone: Zone::Home };
+    /// Walls within that can be broken down.
+    fn wooden(map: &mut Map, bottom_left: Position, top_right: Position) {
+        let walls: Vec<Position> = map.region(bottom_left, top_right)
+            .filter_map(|(position, tile)| matches!(tile, Tile::Wall).then(|| position))
+            .collect();
+        for position in walls {
+            map.set_durability(position, WOODEN_WALL_DURABILITY);
+        }
+    }
+    fn offroad(map: &mut Map, bottom_left: Position, top_right: Position) {
+        let free: Vec<Position> = map.region(bottom_left, top_right)
+            .filter_map(|(position, tile)| matches!(tile, Tile::Ground { occupier: None, .. }).then(|| position))
+            .collect();
+        let ground = map.ground(Zone::Offroad);
+        for position in free {
+            map.set_tile(position, ground.clone());
         }
     }
+    fn home_entrance(commands: &mut Commands, map: &mut Map, position: Position, key: Option<&str>, owner: &str) {
+        let ground = map.ground(Zone::home(owner));
+        map.set_tile(position, ground);
+        spawn_door(commands, map, position, key, key.is_some());
+    }
 
     {
         const MIN_HOME_POSITION: Position = Position::new(60, 8);
         const MAX_HOME_POSITION: Position = Position::new(160, 44);
-        const OFFSET: usize = 2;
-        const MIN_POSITION_X: usize = MIN_HOME_POSITION.x as usize + OFFSET;
-        const MAX_POSITION_X: usize = MAX_HOME_POSITION.x as usize - OFFSET;
-        const MIN_POSITION_Y: usize = MIN_HOME_POSITION.y as usize + OFFSET;
-        const MAX_POSITION_Y: usize = MAX_HOME_POSITION.y as usize - OFFSET;
+        const OFFSET: i32 = 2;
+        const MIN_POSITION_X: i32 = MIN_HOME_POSITION.x + OFFSET;
+        const MAX_POSITION_X: i32 = MAX_HOME_POSITION.x - OFFSET;
+        const MIN_POSITION_Y: i32 = MIN_HOME_POSITION.y + OFFSET;
+        const MAX_POSITION_Y: i32 = MAX_HOME_POSITION.y - OFFSET;
         home(map, MIN_HOME_POSITION, MAX_HOME_POSITION, Zone::KrillTheater { zone: KrillTheaterZone::Free });
 
         for y in [
-            (MIN_POSITION_Y, Position::new(MAX_POSITION_X as i32, MIN_POSITION_Y as i32)),
-            (MAX_POSITION_Y, Position::new(MIN_POSITION_X as i32, MAX_POSITION_Y as i32)),
+            (MIN_POSITION_Y, Position::new(MAX_POSITION_X, MIN_POSITION_Y)),
+            (MAX_POSITION_Y, Position::new(MIN_POSITION_X, MAX_POSITION_Y)),
         ] {
             for x in MIN_POSITION_X..=MAX_POSITION_X {
-                map.set_krill_theater_lineup(x, y.0, y.1, data);
+                map.set_krill_theater_lineup(Position::new(x, y.0), y.1, data);
             }
         }
         for x in [
-            (MIN_POSITION_X, Position::new(MIN_POSITION_X as i32, MIN_POSITION_Y as i32)),
-            (MAX_POSITION_X, Position::new(MAX_POSITION_X as i32, MAX_POSITION_Y as i32)),
+            (MIN_POSITION_X, Position::new(MIN_POSITION_X, MIN_POSITION_Y)),
+            (MAX_POSITION_X, Position::new(MAX_POSITION_X, MAX_POSITION_Y)),
         ] {
             for y in MIN_POSITION_Y..=MAX_POSITION_Y {
-                map.set_krill_theater_lineup(x.0, y, x.1, data);
+                map.set_krill_theater_lineup(Position::new(x.0, y), x.1, data);
             }
         }
         map.set_krill_theater_lineup(
-            MAX_POSITION_X, MAX_POSITION_Y,
-            Position::new(MIN_POSITION_X as i32, MAX_POSITION_Y as i32),
+            Position::new(MAX_POSITION_X, MAX_POSITION_Y),
+            Position::new(MIN_POSITION_X, MAX_POSITION_Y),
             data,
         );
         map.set_krill_theater_lineup(
-            MIN_POSITION_X, MAX_POSITION_Y,
-            Position::new(MIN_POSITION_X as i32, MIN_POSITION_Y as i32),
+            Position::new(MIN_POSITION_X, MAX_POSITION_Y),
+            Position::new(MIN_POSITION_X, MIN_POSITION_Y),
             data,
         );
         map.set_krill_theater_lineup(
-            MIN_POSITION_X, MIN_POSITION_Y,
-            Position::new(MAX_POSITION_X as i32, MIN_POSITION_Y as i32),
+            Position::new(MIN_POSITION_X, MIN_POSITION_Y),
+            Position::new(MAX_POSITION_X, MIN_POSITION_Y),
             data,
         );
         map.set_krill_theater_lineup(
-            MAX_POSITION_X, MIN_POSITION_Y,
-            Position::new(MAX_POSITION_X as i32, MAX_POSITION_Y as i32),
+            Position::new(MAX_POSITION_X, MIN_POSITION_Y),
+            Position::new(MAX_POSITION_X, MAX_POSITION_Y),
             data,
         );
 
@@ -138,9 +148,9 @@ pub fn town(commands: &mut Commands, map: &mut Map, data: &PathfinderGlobalData)
                 let bottom_left = theater_start + Position::new(0, offset_y);
                 let top_right = theater_start + Position::new(offset_x, 0);
                 obstacle(map, bottom_left, top_right);
-                if let Some(tile) = map.get_mut(top_right.x as usize + 1, top_right.y as usize + offset_y as usize) {
-                    *tile = Tile::Wall;
-                }
+                let wall = top_right + Position::new(1, offset_y);
+                map.set_tile(wall, Tile::Wall);
+                wooden(map, wall, wall);
             }
             theater_start.x += offset_x + 6;
         }
@@ -151,9 +161,9 @@ pub fn town(commands: &mut Commands, map: &mut Map, data: &PathfinderGlobalData)
                 let bottom_left = theater_start + Position::new(-offset_x, offset_y);
                 let top_right = theater_start;
                 obstacle(map, bottom_left, top_right);
-                if let Some(tile) = map.get_mut(bottom_left.x as usize - 1, top_right.y as usize + offset_y as usize) {
-                    *tile = Tile::Wall;
-                }
+                let wall = Position::new(bottom_left.x - 1, top_right.y + offset_y);
+                map.set_tile(wall, Tile::Wall);
+                wooden(map, wall, wall);
             }
             theater_start.x -= offset_x + 6;
         }
@@ -202,62 +212,67 @@ pub fn town(commands: &mut Commands, map: &mut Map, data: &PathfinderGlobalData)
         const THEATER_WIDTH: i32 = MAX_THEATER_POSITION.x - MIN_THEATER_POSITION.x;
         for position in [
             // MAIN GATES
-            (MIN_HOME_POSITION.x as usize + (HOME_WIDTH as usize / 2 as usize), MAX_HOME_POSITION.y as usize),
-            (MIN_HOME_POSITION.x as usize + (HOME_WIDTH as usize / 2 as usize) + 1, MAX_HOME_POSITION.y as usize),
-            (MIN_HOME_POSITION.x as usize + (HOME_WIDTH as usize / 2 as usize) - 1, MAX_HOME_POSITION.y as usize),
+            Position::new(MIN_HOME_POSITION.x + HOME_WIDTH / 2, MAX_HOME_POSITION.y),
+            Position::new(MIN_HOME_POSITION.x + HOME_WIDTH / 2 + 1, MAX_HOME_POSITION.y),
+            Position::new(MIN_HOME_POSITION.x + HOME_WIDTH / 2 - 1, MAX_HOME_POSITION.y),
             // BACK EXITS
-            (MIN_HOME_POSITION.x as usize + 9, MIN_HOME_POSITION.y as usize),
-            (MAX_HOME_POSITION.x as usize - 9, MIN_HOME_POSITION.y as usize),
+            Position::new(MIN_HOME_POSITION.x + 9, MIN_HOME_POSITION.y),
+            Position::new(MAX_HOME_POSITION.x - 9, MIN_HOME_POSITION.y),
             // THEATER GATES
-            (MIN_THEATER_POSITION.x as usize + (THEATER_WIDTH as usize / 2 as usize), MAX_THEATER_POSITION.y as usize),
-            (MIN_THEATER_POSITION.x as usize + (THEATER_WIDTH as usize / 2 as usize) + 1, MAX_THEATER_POSITION.y as usize),
-            (MIN_THEATER_POSITION.x as usize + (THEATER_WIDTH as usize / 2 as usize) - 1, MAX_THEATER_POSITION.y as usize),
+            Position::new(MIN_THEATER_POSITION.x + THEATER_WIDTH / 2, MAX_THEATER_POSITION.y),
+            Position::new(MIN_THEATER_POSITION.x + THEATER_WIDTH / 2 + 1, MAX_THEATER_POSITION.y),
+            Position::new(MIN_THEATER_POSITION.x + THEATER_WIDTH / 2 - 1, MAX_THEATER_POSITION.y),
             // KITCHEN DOOR
-            (MAX_KITCHEN_POSITION.x as usize, MAX_KITCHEN_POSITION.y as usize - 2),
+            Position::new(MAX_KITCHEN_POSITION.x, MAX_KITCHEN_POSITION.y - 2),
             // KITCHEN INNER-DOOR
-            (MAX_KITCHEN_POSITION.x as usize - 2, MIN_KITCHEN_POSITION.y as usize + 6),
+            Position::new(MAX_KITCHEN_POSITION.x - 2, MIN_KITCHEN_POSITION.y + 6),
         ] {
-            if let Some(tile) = map.get_mut(position.0, position.1) {
-                *tile = Tile::new_ground(Zone::KrillTheater { zone: KrillTheaterZone::Free });
-            }
+            let ground = map.ground(Zone::KrillTheater { zone: KrillTheaterZone::Free });
+            map.set_tile(position, ground);
         }
     }
 
     let position = Position::new(29, 29);
-    home(map, position, position + Position::new(10, 6), Zone::Home);
-    home_entrance(map, position + Position::new(10, 2));
-    home(map, position + Position::new(6, 6), position + Position::new(10, 10), Zone::Home);
-    home_entrance(map, position + Position::new(7, 6));
+    home(map, position, position + Position::new(10, 6), Zone::home("Hilde"));
+    home_entrance(commands, map, position + Position::new(10, 2), None, "Hilde");
+    home(map, position + Position::new(6, 6), position + Position::new(10, 10), Zone::home("Hilde"));
+    home_entrance(commands, map, position + Position::new(7, 6), None, "Hilde");
     spawn_chest(commands, map, position + Position::new(7, 9), Inventory::new(
         vec![
             Box::new(Item::new_apple()),
             Box::new(Item::new_banana()),
             Box::new(Item::new_apple()),
+            Box::new(Item::new_key("Storehouse")),
         ],
     ));
     let position = Position::new(29, 49);
-    home(map, position, position + Position::new(10, 10), Zone::Home);
-    home_entrance(map, position + Position::new(0, 8));
-    home_entrance(map, position + Position::new(8, 0));
+    home(map, position, position + Position::new(10, 10), Zone::home("Oswin"));
+    home_entrance(commands, map, position + Position::new(0, 8), None, "Oswin");
+    home_entrance(commands, map, position + Position::new(8, 0), None, "Oswin");
     let position = Position::new(49, 49);
-    home(map, position, position + Position::new(10, 10), Zone::Home);
-    home_entrance(map, position + Position::new(2, 0));
-    home_entrance(map, position + Position::new(10, 8));
+    home(map, position, position + Position::new(10, 10), Zone::home("Marlo"));
+    home_entrance(commands, map, position + Position::new(2, 0), None, "Marlo");
+    home_entrance(commands, map, position + Position::new(10, 8), None, "Marlo");
     let position = Position::new(200, 60);
-    home(map, position, position + Position::new(10, 8), Zone::Home);
-    home_entrance(map, position + Position::new(0, 2));
+    home(map, position, position + Position::new(10, 8), Zone::home("Brannoc"));
+    wooden(map, position, position + Position::new(10, 8));
+    home_entrance(commands, map, position + Position::new(0, 2), Some("Storehouse"), "Brannoc");
     spawn_chest(commands, map, position + Position::new(9, 1), Inventory::new(
         vec![
             Box::new(Item::new_pistol()),
         ],
     ));
     let position = Position::new(200, 72);
-    home(map, position, position + Position::new(10, 10), Zone::Home);
-    home_entrance(map, position + Position::new(0, 2));
+    home(map, position, position + Position::new(10, 10), Zone::home("Tamsin"));
+    home_entrance(commands, map, position + Position::new(0, 2), None, "Tamsin");
     let position = Position::new(186, 72);
-    home(map, position, position + Position::new(10, 10), Zone::Home);
-    home_entrance(map, position + Position::new(0, 2));
-    home_entrance(map, position + Position::new(10, 2));
+    home(map, position, position + Position::new(10, 10), Zone::home("Edric"));
+    home_entrance(commands, map, position + Position::new(0, 2), None, "Edric");
+    home_entrance(commands, map, position + Position::new(10, 2), None, "Edric");
+
+    // Field and woods.
+    offroad(map, Position::new(8, 34), Position::new(27, 48));
+    offroad(map, Position::new(170, 2), Position::new(218, 50));
 
     obstacle(map, Position::new(12, 38), Position::new(13, 46));
     for pos in [Position::new(18, 38), Position::new(21, 38), Position::new(24, 38), Position::new(27, 38)] {
@@ -275,9 +290,34 @@ pub fn town(commands: &mut Commands, map: &mut Map, data: &PathfinderGlobalData)
             entity_commands.insert(crate::character::PlayerTag);
         },
     );
-    spawn_lerain(commands, map, Position::new(50, 8));
-    spawn_lerain(commands, map, Position::new(20, 40));
-    spawn_lerain(commands, map, Position::new(30, 10));
-    spawn_lerain(commands, map, Position::new(25, 20));
-    spawn_werewolf(commands, map, Position::new(2, 4));
+    spawn_lerain(commands, map, "Hilde", Position::new(50, 8), schedules.routine("Cook", "WestCottage"), vec![Item::new_apple()]);
+    spawn_lerain(commands, map, "Oswin", Position::new(20, 40), schedules.routine("Farmer", "FieldHouse"), Vec::new());
+    spawn_lerain(commands, map, "Marlo", Position::new(30, 10), schedules.routine("Actor", "NorthHouse"), Vec::new());
+    spawn_lerain(commands, map, "Tamsin", Position::new(25, 20), schedules.routine("Patron", "EastHouse"), Vec::new());
+    spawn_rumdare(commands, map, "Brannoc", Position::new(80, 60), schedules.patrol("Market"));
+    spawn_rumdare(commands, map, "Yseult", Position::new(100, 2), schedules.patrol("Outskirts"));
+    spawn_werewolf(commands, map, "Edric", Position::new(2, 4), schedules.routine("Patron", "RiverHouse"));
+
+    if let Err(error) = data.validate(map) {
+        panic!("invalid pathfinder points: {error}");
+    }
+}
+
+/// The cellar under the storehouse, down the stairs in its corner.
+pub fn cellar(commands: &mut Commands, map: &mut Map, levels: &mut Levels) {
+    const STAIRS_DOWN: Position = Position::new(208, 66);
+    const STAIRS_UP: Position = Position::new(2, 9);
+    let mut cellar = Map::new(24, 12);
+    cellar.set_level(CELLAR);
+    let ground = cellar.ground(Zone::home("Brannoc"));
+    cellar.create_room(Position::new(0, 0), Position::new(23, 11), Tile::Wall, ground);
+    spawn_stairs(commands, map, STAIRS_DOWN, CELLAR, STAIRS_UP + Position::new(1, 0));
+    spawn_stairs(commands, &mut cellar, STAIRS_UP, LevelId::SURFACE, STAIRS_DOWN - Position::new(1, 0));
+    spawn_chest(commands, &mut cellar, Position::new(21, 2), Inventory::new(
+        vec![
+            Box::new(Item::new_banana()),
+            Box::new(Item::new_banana()),
+        ],
+    ));
+    levels.add(cellar, Inactive::Simulated);
 }
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, BinaryHeap};
+use bevy::prelude::{Entity, ResMut};
+use crate::physics::{Map, Position, Tile};
+
+/// Ticks a noise lingers before fading.
+const NOISE_TICKS: u32 = 2;
+/// Volume lost when sound passes through a wall.
+const WALL_DAMPING: u32 = 8;
+/// Volume lost when sound passes through an obstacle.
+const OBSTACLE_DAMPING: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseKind {
+    /// Character moving about.
+    Footsteps,
+    Gunshot,
+    /// Werewolf mauling its prey.
+    Attack,
+}
+impl NoiseKind {
+    pub const fn volume(&self) -> u32 {
+        match self {
+            NoiseKind::Footsteps => 4,
+            NoiseKind::Gunshot => 30,
+            NoiseKind::Attack => 15,
+        }
+    }
+    /// True if humans should run from it.
+    pub const fn is_alarming(&self) -> bool {
+        match self {
+            NoiseKind::Footsteps => false,
+            NoiseKind::Gunshot | NoiseKind::Attack => true,
+        }
+    }
+}
+
+pub struct NoiseEvent {
+    pub kind: NoiseKind,
+    pub origin: Position,
+    pub source: Option<Entity>,
+    /// Volume heard on every tile the noise reached.
+    pub heard: HashMap<Position, u32>,
+    ticks_left: u32,
+}
+
+#[derive(Default)]
+pub struct Noise {
+    events: Vec<NoiseEvent>,
+}
+impl Noise {
+    pub fn emit(&mut self, map: &Map, kind: NoiseKind, origin: Position, source: Option<Entity>) {
+        self.events.push(NoiseEvent {
+            kind,
+            origin,
+            source,
+            heard: propagate(map, origin, kind.volume()),
+            ticks_left: NOISE_TICKS,
+        });
+    }
+    pub fn events(&self) -> &Vec<NoiseEvent> {
+        &self.events
+    }
+    /// Loudest noise heard at the position which passes the filter.
+    pub fn loudest_at<F: Fn(&NoiseEvent) -> bool>(&self, position: &Position, filter: F) -> Option<(&NoiseEvent, u32)> {
+        self.events.iter()
+            .filter(|event| filter(event))
+            .filter_map(|event| event.heard.get(position).map(|volume| (event, *volume)))
+            .max_by_key(|(_, volume)| *volume)
+    }
+    /// Sum of all noise heard at the position.
+    pub fn volume_at(&self, position: &Position) -> u32 {
+        self.events.iter()
+            .filter_map(|event| event.heard.get(position))
+            .sum()
+    }
+}
+
+/// Flood fill outwards from the origin,
+/// losing volume with every step and more so through walls.
+fn propagate(map: &Map, origin: Position, volume: u32) -> HashMap<Position, u32> {
+    let mut heard = HashMap::<Position, u32>::new();
+    let mut open = BinaryHeap::<(u32, i32, i32)>::new();
+    if origin.get_from_map(map).is_none() {
+        return heard;
+    }
+    heard.insert(origin, volume);
+    open.push((volume, origin.x, origin.y));
+    while let Some((volume, x, y)) = open.pop() {
+        let position = Position::new(x, y);
+        if heard.get(&position).map_or(false, |v| *v > volume) {
+            continue;
+        }
+        for offset in [Position::new(0, 1), Position::new(1, 0), Position::new(0, -1), Position::new(-1, 0)] {
+            let neighbor = position + offset;
+            let damping = match neighbor.get_from_map(map) {
+                Some(Tile::Ground { .. }) => 1,
+                Some(Tile::Obstacle { .. }) => OBSTACLE_DAMPING,
+                Some(Tile::Wall) => WALL_DAMPING,
+                None => continue,
+            };
+            if volume <= damping {
+                continue;
+            }
+            let new_volume = volume - damping;
+            if heard.get(&neighbor).map_or(true, |v| *v < new_volume) {
+                heard.insert(neighbor, new_volume);
+                open.push((new_volume, neighbor.x, neighbor.y));
+            }
+        }
+    }
+    heard
+}
+
+/// Fades out old noises, run at the start of every tick.
+pub fn noise_update(mut noise: ResMut<Noise>) {
+    for event in noise.events.iter_mut() {
+        event.ticks_left -= 1;
+    }
+    noise.events.retain(|event| event.ticks_left != 0);
+}
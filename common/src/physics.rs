@@ -1,46 +1,84 @@
-use std::{hash::Hash, collections::HashSet};
+use std::{hash::Hash, collections::{HashMap, HashSet}};
 use bevy::{
     ecs::system::EntityCommands,
     prelude::{Entity, Commands, World, FromWorld, Component},
 };
 use crate::{
-    character::{CharacterBundle, Interact, CharacterType, Health, ActionHistory, MovementInput, CharacterData},
+    character::{CharacterBundle, Interact, CharacterType, Health, ActionHistory, MovementInput, CharacterData, TerrainDelay},
     map_setup::town,
     inventory::{Equipment, Inventory, Item}, behaviors::pathfinder::data::PathfinderGlobalData,
+    level::LevelId,
+    chunks::{Chunks, chunk_of},
 };
 use bitflags::bitflags;
 
-#[derive(Clone)]
+/// Hits an obstacle takes before it breaks.
+const OBSTACLE_DURABILITY: u32 = 3;
+/// Vision worked out from this many places is remembered at most.
+const VISION_CACHE_SIZE: usize = 256;
+
+#[derive(Clone, PartialEq)]
 pub enum Zone {
     Road,
     Offroad,
-    Home,
+    /// What is left of a broken wall or obstacle.
+    Rubble,
+    Home {
+        /// Name of whoever lives there.
+        owner: Option<String>,
+    },
     KrillTheater { zone: KrillTheaterZone },
 }
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum KrillTheaterZone {
     Free,
     LineUp(Position),
     Exit,
 }
-impl Tile {
-    pub fn krill_theater(&self) -> Option<&KrillTheaterZone> {
+impl Zone {
+    pub fn home(owner: &str) -> Zone {
+        Zone::Home { owner: Some(owner.to_string()) }
+    }
+    /// Ticks it takes to step onto ground of this zone.
+    pub fn move_ticks(&self) -> u32 {
         match self {
-            Tile::Ground { zone, .. } => if let Zone::KrillTheater { zone } = zone {
-                Some(zone)
-            } else {
-                None
-            },
-            _ => None,
+            Zone::Offroad | Zone::Rubble => 2,
+            _ => 1,
+        }
+    }
+    /// Cost of walking through when planning a path, roads are preferred.
+    pub fn path_cost(&self) -> u32 {
+        match self {
+            Zone::Road => 1,
+            _ => self.move_ticks() + 1,
+        }
+    }
+    pub fn krill_theater(&self) -> Option<&KrillTheaterZone> {
+        if let Zone::KrillTheater { zone } = self {
+            Some(zone)
+        } else {
+            None
         }
     }
 }
+/// Index of a zone in the map's palette, ground keeps these instead of the zone itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ZoneId(u16);
+impl ZoneId {
+    /// Every palette starts with road.
+    pub const ROAD: ZoneId = ZoneId(0);
+}
+impl From<u16> for ZoneId {
+    fn from(value: u16) -> Self {
+        ZoneId(value)
+    }
+}
 /// Enum value true if space is occupied, otherwise false.
 #[derive(Clone)]
 pub enum Tile {
     Ground {
         occupier: Option<Occupier>,
-        zone: Zone,
+        zone: ZoneId,
     },
     Wall,
     Obstacle {
@@ -48,6 +86,14 @@ pub enum Tile {
     },
 }
 impl Tile {
+    /// Walls and closed doors cannot be seen through.
+    pub fn blocks_vision(&self) -> bool {
+        match self {
+            Tile::Wall => true,
+            Tile::Ground { occupier: Some(occupier), .. } => occupier.sprite == crate::character::Sprite::Door,
+            _ => false,
+        }
+    }
     pub fn is_character(&self) -> bool {
         if let Tile::Ground { occupier, .. } = self {
             if let Some(occupier) = occupier {
@@ -106,11 +152,11 @@ pub enum CollisionType {
     Sensor,
 }
 impl Tile {
-    pub fn new_ground(zone: Zone) -> Self {
+    pub fn new_ground(zone: ZoneId) -> Self {
         Tile::Ground { occupier: None, zone }
     }
     pub fn default_ground() -> Self {
-        Self::new_ground(Zone::Road)
+        Self::new_ground(ZoneId::ROAD)
     }
     pub fn is_occupied(&self, collision: &mut Collision) -> bool {
         match self {
@@ -119,45 +165,138 @@ impl Tile {
                 collision.collided = true;
                 true
             },
-            Tile::Obstacle { occupier } => !matches!(collision.collision_type, CollisionType::Sensor) || occupier.is_some(),
+            Tile::Obstacle { occupier } => {
+                if occupier.is_none() {
+                    // Stops everything, projectiles included, which hit it instead.
+                    collision.collided = true;
+                }
+                true
+            },
         }
     }
 }
 pub struct Map {
-    values: Vec::<Tile>,
+    tiles: Chunks,
     size_x: usize,
     size_y: usize,
+    /// Zones the ground refers to by id.
+    zones: Vec<Zone>,
+    /// Chunk the simulation is centered on and how many chunks around it are simulated.
+    active_area: Option<((usize, usize), usize)>,
+    /// Bumped whenever the layout changes, occupiers coming and going do not count.
+    revision: u64,
+    /// Bumped whenever what can be seen through changes, the layout or doors.
+    sight_revision: u64,
+    /// Hits left on tiles that can be broken.
+    durability: HashMap<Position, u32>,
+    level: LevelId,
 }
-impl Map {
-    pub fn new<const X: usize, const Y: usize>() -> Map {
-        let mut values = Vec::<Tile>::with_capacity(X * Y);
-        Self::fill_values::<X, Y>(&mut values);
-        Map {
-            values,
-            size_x: X,
-            size_y: Y,
+/// Why tiles could not be made into a map.
+#[derive(Debug)]
+pub enum MapError {
+    /// The number of tiles does not fill the size given.
+    SizeMismatch { size_x: usize, size_y: usize, tiles: usize },
+    /// Ground refers to a zone missing from the palette.
+    UnknownZone(ZoneId),
+}
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapError::SizeMismatch { size_x, size_y, tiles } => write!(f, "{tiles} tiles do not fill a {size_x}x{size_y} map"),
+            MapError::UnknownZone(zone) => write!(f, "zone {} is not in the palette", zone.0),
         }
     }
-    pub fn initialize<const X: usize, const Y: usize>(&mut self) {
-        self.values.clear();
-        Self::fill_values::<X, Y>(&mut self.values);
-        self.size_x = X;
-        self.size_y = Y;
-    }
-    fn fill_values<const X: usize, const Y: usize>(values: &mut Vec::<Tile>) {
-        for _ in 0..X * Y {
-            values.push(Tile::default_ground());
+}
+
+impl Map {
+    /// Ground walled in on every side.
+    pub fn new(size_x: usize, size_y: usize) -> Map {
+        let mut map = Map {
+            tiles: Chunks::new(0, 0),
+            size_x: 0,
+            size_y: 0,
+            zones: vec![Zone::Road],
+            active_area: None,
+            revision: 0,
+            sight_revision: 0,
+            durability: HashMap::new(),
+            level: LevelId::SURFACE,
+        };
+        map.initialize(size_x, size_y);
+        map
+    }
+    /// Lays out the tiles given row by row, starting from the bottom left,
+    /// their ground refers to the zones given.
+    pub fn from_tiles(size_x: usize, size_y: usize, zones: Vec<Zone>, tiles: Vec<Tile>) -> Result<Map, MapError> {
+        if tiles.len() != size_x * size_y {
+            return Err(MapError::SizeMismatch { size_x, size_y, tiles: tiles.len() });
         }
-        
-        // Borders
-        for y in 0..Y {
-            values[X * y] = Tile::Wall;
-            values[X - 1 + (X * y)] = Tile::Wall;
+        if let Some(Tile::Ground { zone, .. }) = tiles.iter().find(|tile| matches!(tile, Tile::Ground { zone, .. } if zone.0 as usize >= zones.len())) {
+            return Err(MapError::UnknownZone(*zone));
         }
-        for x in 0..X {
-            values[x] = Tile::Wall;
-            values[x + (X * (Y - 1))] = Tile::Wall;
+        let mut map = Map::new(0, 0);
+        map.tiles = Chunks::new(size_x, size_y);
+        map.size_x = size_x;
+        map.size_y = size_y;
+        map.zones = zones;
+        for (index, tile) in tiles.into_iter().enumerate() {
+            let position = Position::new((index % size_x) as i32, (index / size_x) as i32);
+            map.set_tile(position, tile);
         }
+        Ok(map)
+    }
+    /// Starts over with ground walled in on every side.
+    pub fn initialize(&mut self, size_x: usize, size_y: usize) {
+        self.tiles = Chunks::new(size_x, size_y);
+        self.size_x = size_x;
+        self.size_y = size_y;
+        self.zones = vec![Zone::Road];
+        self.durability.clear();
+        self.wall_borders();
+        self.revision += 1;
+        self.sight_revision += 1;
+    }
+    /// Keeps the tiles that still fit, new ground is walled in on every side.
+    pub fn resize(&mut self, size_x: usize, size_y: usize) {
+        let mut tiles = Chunks::new(size_x, size_y);
+        for y in 0..size_y.min(self.size_y) {
+            for x in 0..size_x.min(self.size_x) {
+                let tile = self.tiles.get(x, y);
+                if !matches!(tile, Tile::Ground { occupier: None, zone: ZoneId::ROAD }) {
+                    *tiles.get_mut(x, y) = tile.clone();
+                }
+            }
+        }
+        self.tiles = tiles;
+        self.size_x = size_x;
+        self.size_y = size_y;
+        self.durability.retain(|position, _| matches!(position.to_coordinates(), Some((x, y)) if x < size_x && y < size_y));
+        self.wall_borders();
+        self.revision += 1;
+        self.sight_revision += 1;
+    }
+    fn wall_borders(&mut self) {
+        let (size_x, size_y) = (self.size_x, self.size_y);
+        if size_x == 0 || size_y == 0 { return; }
+        for y in 0..size_y {
+            *self.tiles.get_mut(0, y) = Tile::Wall;
+            *self.tiles.get_mut(size_x - 1, y) = Tile::Wall;
+        }
+        for x in 0..size_x {
+            *self.tiles.get_mut(x, 0) = Tile::Wall;
+            *self.tiles.get_mut(x, size_y - 1) = Tile::Wall;
+        }
+        self.durability.retain(|position, _| matches!(
+            position.to_coordinates(),
+            Some((x, y)) if x != 0 && y != 0 && x != size_x - 1 && y != size_y - 1
+        ));
+    }
+    /// Does stepping diagonally by the offset squeeze past the corner of a wall or obstacle?
+    pub fn cuts_corner(&self, position: &Position, offset: &Position) -> bool {
+        if offset.x == 0 || offset.y == 0 { return false; }
+        [Position::new(position.x + offset.x, position.y), Position::new(position.x, position.y + offset.y)]
+            .iter()
+            .any(|p| matches!(p.get_from_map(self), Some(Tile::Wall | Tile::Obstacle { .. }) | None))
     }
     pub fn get_size_x(&self) -> usize {
         self.size_x
@@ -165,6 +304,119 @@ impl Map {
     pub fn get_size_y(&self) -> usize {
         self.size_y
     }
+    /// The id of the zone, added to the palette if new.
+    pub fn zone_id(&mut self, zone: Zone) -> ZoneId {
+        if let Some(index) = self.zones.iter().position(|z| *z == zone) {
+            return ZoneId(index as u16);
+        }
+        self.zones.push(zone);
+        ZoneId((self.zones.len() - 1) as u16)
+    }
+    pub fn zone(&self, id: ZoneId) -> &Zone {
+        self.zones.get(id.0 as usize).unwrap_or(&Zone::Road)
+    }
+    /// Empty ground of the zone.
+    pub fn ground(&mut self, zone: Zone) -> Tile {
+        Tile::new_ground(self.zone_id(zone))
+    }
+    /// Zone of the ground at the position.
+    pub fn zone_at(&self, position: &Position) -> Option<&Zone> {
+        match position.get_from_map(self) {
+            Some(Tile::Ground { zone, .. }) => Some(self.zone(*zone)),
+            _ => None,
+        }
+    }
+    /// Ticks it takes to step onto the position.
+    pub fn move_ticks(&self, position: &Position) -> u32 {
+        self.zone_at(position).map_or(1, Zone::move_ticks)
+    }
+    /// Cost of walking onto the position when planning a path.
+    pub fn path_cost(&self, position: &Position) -> u32 {
+        self.zone_at(position).map_or(1, Zone::path_cost)
+    }
+    /// Tiles within the corners given that are on the map.
+    pub fn region(&self, bottom_left: Position, top_right: Position) -> impl Iterator<Item = (Position, &Tile)> + '_ {
+        (bottom_left.y..=top_right.y)
+            .flat_map(move |y| (bottom_left.x..=top_right.x).map(move |x| Position::new(x, y)))
+            .filter_map(move |position| {
+                let (x, y) = position.to_coordinates()?;
+                self.get(x, y).map(|tile| (position, tile))
+            })
+    }
+    /// Chunks holding tiles of their own, the rest are plain road.
+    pub fn allocated_chunks(&self) -> usize {
+        self.tiles.allocated()
+    }
+    /// Simulates only the chunks up to the distance from the chunk of the position.
+    pub fn set_active_area(&mut self, position: &Position, distance: usize) {
+        self.active_area = chunk_of(position).map(|chunk| (chunk, distance));
+    }
+    /// Is the position within the simulated chunks? Everywhere is unless an area is set.
+    pub fn is_active(&self, position: &Position) -> bool {
+        match (self.active_area, chunk_of(position)) {
+            (None, _) => true,
+            (Some(((center_x, center_y), distance)), Some((x, y))) => center_x.abs_diff(x) <= distance && center_y.abs_diff(y) <= distance,
+            (Some(_), None) => false,
+        }
+    }
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    /// The level the map lays out, given to everything spawned on it.
+    pub fn level(&self) -> LevelId {
+        self.level
+    }
+    pub fn set_level(&mut self, level: LevelId) {
+        self.level = level;
+    }
+    pub fn sight_revision(&self) -> u64 {
+        self.sight_revision
+    }
+    /// Call when something that blocks vision comes or goes without the layout changing.
+    pub fn sight_changed(&mut self) {
+        self.sight_revision += 1;
+    }
+    /// Replaces the tile, use for any change to the layout.
+    /// Obstacles can be broken, anything else placed cannot.
+    pub fn set_tile(&mut self, position: Position, tile: Tile) {
+        let is_obstacle = matches!(tile, Tile::Obstacle { .. });
+        if let Some(t) = position.get_mut_from_map(self) {
+            *t = tile;
+            self.revision += 1;
+            self.sight_revision += 1;
+            if is_obstacle {
+                self.durability.insert(position, OBSTACLE_DURABILITY);
+            } else {
+                self.durability.remove(&position);
+            }
+        }
+    }
+    /// Lets the tile be broken after the hits given.
+    pub fn set_durability(&mut self, position: Position, hits: u32) {
+        if position.get_from_map(self).is_some() {
+            self.durability.insert(position, hits);
+        }
+    }
+    pub fn is_breakable(&self, position: &Position) -> bool {
+        self.durability.contains_key(position)
+    }
+    /// Wears the tile down, true if it broke into rubble.
+    pub fn damage_tile(&mut self, position: &Position, damage: u32) -> bool {
+        let hits = match self.durability.get_mut(position) {
+            Some(hits) => hits,
+            None => return false,
+        };
+        *hits = hits.saturating_sub(damage);
+        if *hits != 0 { return false; }
+        // Whatever sat on the obstacle is left lying in the rubble.
+        let occupier = match position.get_mut_from_map(self) {
+            Some(Tile::Obstacle { occupier }) => occupier.take(),
+            _ => None,
+        };
+        let zone = self.zone_id(Zone::Rubble);
+        self.set_tile(*position, Tile::Ground { occupier, zone });
+        true
+    }
     pub fn spawn<F: FnOnce(EntityCommands)>(
         &mut self,
         commands: &mut Commands,
@@ -174,19 +426,20 @@ impl Map {
         collision_type: CollisionType,
         spawned_callback: F,
     ) {
-        if let Some(tile) = self.get_mut(position.x as usize, position.y as usize) {
+        if let Some(tile) = position.get_mut_from_map(self) {
             if let Tile::Ground { occupier, .. } | Tile::Obstacle { occupier } = tile {
                 let mut entity = commands.spawn();
                 *occupier = Some(Occupier::new(entity.id(), sprite, collision_type, None));
                 entity
                     .insert(sprite)
                     .insert(position)
-                    .insert(velocity);
+                    .insert(velocity)
+                    .insert(self.level);
                 spawned_callback(entity);
             }
         }
     }
-    pub fn spawn_character(
+    pub fn spawn_character<F: FnOnce(EntityCommands)>(
         &mut self,
         commands: &mut Commands,
         sprite: crate::character::Sprite,
@@ -194,9 +447,9 @@ impl Map {
         health: Health,
         character_type: CharacterType,
         character_data: CharacterData,
-        spawned_callback: fn(EntityCommands),
+        spawned_callback: F,
     ) {
-        if let Some(tile) = self.get_mut(position.x as usize, position.y as usize) {
+        if let Some(tile) = position.get_mut_from_map(self) {
             if let Tile::Ground {
                 occupier: ref mut occupier_option,
                 ..
@@ -219,13 +472,15 @@ impl Map {
                     ),
                     equipment: Equipment::default(),
                     collision: Collision::new(CollisionType::Solid),
+                    terrain_delay: TerrainDelay::default(),
+                    level: self.level,
                 });
                 spawned_callback(entity);
             }
         }
     }
-    pub fn destroy(&mut self, x: usize, y: usize, commands: &mut Commands) {
-        if let Some(tile) = self.get_mut(x, y) {
+    pub fn destroy(&mut self, position: &Position, commands: &mut Commands) {
+        if let Some(tile) = position.get_mut_from_map(self) {
             if let Tile::Ground { occupier, .. } | Tile::Obstacle { occupier } = tile {
                 if let Some(o) = occupier {
                     commands.entity(o.entity).despawn();
@@ -236,36 +491,46 @@ impl Map {
     }
     pub fn get(&self, x: usize, y: usize) -> Option<&Tile> {
         if x < self.size_x && y < self.size_y {
-            self.values.get(x + (self.size_x * y))
+            Some(self.tiles.get(x, y))
         } else {
             None
         }
     }
     pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Tile> {
         if x < self.size_x && y < self.size_y {
-            self.values.get_mut(x + (self.size_x * y))
+            Some(self.tiles.get_mut(x, y))
         } else {
             None
         }
     }
-    pub fn get_in_vision<'a>(&'a self, map_cache: &'a mut MapCache, position: Position) -> &'a HashSet::<Position> {
-        map_cache.in_vision.clear();
-
-        self.vision_recursion(position.clone(), &mut map_cache.in_vision, |p| p.x += 1, |p| p.y += 1, |p, i| p.x = i.x);
-        self.vision_recursion(position.clone(), &mut map_cache.in_vision, |p| p.x += 1, |p| p.y -= 1, |p, i| p.x = i.x);
-        self.vision_recursion(position.clone(), &mut map_cache.in_vision, |p| p.x -= 1, |p| p.y -= 1, |p, i| p.x = i.x);
-        self.vision_recursion(position, &mut map_cache.in_vision, |p| p.x -= 1, |p| p.y += 1, |p, i| p.x = i.x);
-
-        self.vision_recursion(position.clone(), &mut map_cache.in_vision, |p| p.y += 1, |p| p.x += 1, |p, i| p.y = i.y);
-        self.vision_recursion(position.clone(), &mut map_cache.in_vision, |p| p.y += 1, |p| p.x -= 1, |p, i| p.y = i.y);
-        self.vision_recursion(position.clone(), &mut map_cache.in_vision, |p| p.y -= 1, |p| p.x -= 1, |p, i| p.y = i.y);
-        self.vision_recursion(position, &mut map_cache.in_vision, |p| p.y -= 1, |p| p.x += 1, |p, i| p.y = i.y);
+    /// Tiles seen from the position, up to the distance away.
+    /// Remembered until something that blocks vision changes.
+    pub fn get_in_vision<'a>(&'a self, map_cache: &'a mut MapCache, position: Position, distance: u32) -> &'a HashSet::<Position> {
+        if map_cache.sight_revision != self.sight_revision || map_cache.vision.len() >= VISION_CACHE_SIZE {
+            map_cache.vision.clear();
+            map_cache.sight_revision = self.sight_revision;
+        }
+        map_cache.vision.entry((position, distance)).or_insert_with(|| {
+            let mut in_vision = HashSet::new();
+            self.look_around(position, distance, &mut in_vision);
+            in_vision
+        })
+    }
+    fn look_around(&self, position: Position, distance: u32, in_vision: &mut HashSet::<Position>) {
+        self.vision_recursion(position.clone(), distance, in_vision, |p| p.x += 1, |p| p.y += 1, |p, i| p.x = i.x);
+        self.vision_recursion(position.clone(), distance, in_vision, |p| p.x += 1, |p| p.y -= 1, |p, i| p.x = i.x);
+        self.vision_recursion(position.clone(), distance, in_vision, |p| p.x -= 1, |p| p.y -= 1, |p, i| p.x = i.x);
+        self.vision_recursion(position, distance, in_vision, |p| p.x -= 1, |p| p.y += 1, |p, i| p.x = i.x);
 
-        &map_cache.in_vision
+        self.vision_recursion(position.clone(), distance, in_vision, |p| p.y += 1, |p| p.x += 1, |p, i| p.y = i.y);
+        self.vision_recursion(position.clone(), distance, in_vision, |p| p.y += 1, |p| p.x -= 1, |p, i| p.y = i.y);
+        self.vision_recursion(position.clone(), distance, in_vision, |p| p.y -= 1, |p| p.x -= 1, |p, i| p.y = i.y);
+        self.vision_recursion(position, distance, in_vision, |p| p.y -= 1, |p| p.x += 1, |p, i| p.y = i.y);
     }
     fn vision_recursion(
         &self,
         initial_position: Position,
+        distance: u32,
         in_vision: &mut HashSet::<Position>,
         increment_1_position: fn(&mut Position),
         increment_2_position: fn(&mut Position),
@@ -274,13 +539,12 @@ impl Map {
         let mut max_value: Option<usize> = None;
         let mut position = initial_position.clone();
         let mut insert = |position: Position| {
-            const DISTANCE: u32 = 20;
-            if position.distance(&initial_position) < DISTANCE * DISTANCE {
+            if position.distance(&initial_position) < distance * distance {
                 in_vision.insert(position);
             }
         };
-        while let Some(tile) = self.get(position.x as usize, position.y as usize) {
-            if matches!(tile, Tile::Wall) {
+        while let Some(tile) = position.get_from_map(self) {
+            if tile.blocks_vision() {
                 insert(position.clone());
                 break;
             }
@@ -288,14 +552,14 @@ impl Map {
             let mut saved_position = position.clone();
             increment_1_position(&mut position);
             let mut increment_count = 0;
-            while let Some(tile) = self.get(position.x as usize, position.y as usize) {
+            while let Some(tile) = position.get_from_map(self) {
                 increment_count += 1;
                 if let Some(max_value) = max_value {
                     if increment_count > max_value {
                         break;
                     }
                 }
-                if matches!(tile, Tile::Wall) {
+                if tile.blocks_vision() {
                     insert(position.clone());
                     break;
                 }
@@ -317,29 +581,30 @@ impl Map {
     }
 }
 impl FromWorld for Map {
+    /// Empty until the setup lays it out.
     fn from_world(_world: &mut World) -> Self {
-        Map::new::<60, 30>()
+        Map::new(0, 0)
     }
 }
 #[derive(Default)]
 pub struct MapCache {
-    in_vision: HashSet<Position>,
+    /// Tiles in vision by where they were seen from and how far.
+    vision: HashMap<(Position, u32), HashSet<Position>>,
+    sight_revision: u64,
 }
 
 impl Position {
+    /// Map coordinates, none if either is negative.
+    pub fn to_coordinates(&self) -> Option<(usize, usize)> {
+        Some((usize::try_from(self.x).ok()?, usize::try_from(self.y).ok()?))
+    }
     pub fn get_from_map<'a>(&'a self, map: &'a Map) -> Option<&Tile> {
-        if self.x < 0 || self.y < 0 {
-            None
-        } else {
-            map.get(self.x as usize, self.y as usize)
-        }
+        let (x, y) = self.to_coordinates()?;
+        map.get(x, y)
     }
     pub fn get_mut_from_map<'a>(&'a self, map: &'a mut Map) -> Option<&mut Tile> {
-        if self.x < 0 || self.y < 0 {
-            None
-        } else {
-            map.get_mut(self.x as usize, self.y as usize)
-        }
+        let (x, y) = self.to_coordinates()?;
+        map.get_mut(x, y)
     }
 }
 
@@ -497,3 +762,101 @@ impl Velocity {
         Velocity { movement, speed }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quadrant_of_diagonal_positions() {
+        let origin = Position::new(0, 0);
+        assert_eq!(origin.quadrant(Position::new(3, 2)), Quadrant::TOP_RIGHT);
+        assert_eq!(origin.quadrant(Position::new(3, -2)), Quadrant::BOTTOM_RIGHT);
+        assert_eq!(origin.quadrant(Position::new(-3, -2)), Quadrant::BOTTOM_LEFT);
+        assert_eq!(origin.quadrant(Position::new(-3, 2)), Quadrant::TOP_LEFT);
+    }
+
+    #[test]
+    fn quadrant_of_positions_on_an_axis() {
+        let origin = Position::new(5, 5);
+        assert_eq!(origin.quadrant(Position::new(9, 5)), Quadrant::TOP_RIGHT | Quadrant::BOTTOM_RIGHT);
+        assert_eq!(origin.quadrant(Position::new(1, 5)), Quadrant::TOP_LEFT | Quadrant::BOTTOM_LEFT);
+        assert_eq!(origin.quadrant(Position::new(5, 9)), Quadrant::TOP_LEFT | Quadrant::TOP_RIGHT);
+        assert_eq!(origin.quadrant(Position::new(5, 1)), Quadrant::BOTTOM_LEFT | Quadrant::BOTTOM_RIGHT);
+        assert_eq!(origin.quadrant(origin), Quadrant::all());
+    }
+
+    #[test]
+    fn opposite_of_single_quadrant() {
+        assert_eq!(Quadrant::TOP_RIGHT.opposite(), Quadrant::BOTTOM_LEFT);
+        assert_eq!(Quadrant::BOTTOM_RIGHT.opposite(), Quadrant::TOP_LEFT);
+        assert_eq!(Quadrant::BOTTOM_LEFT.opposite(), Quadrant::TOP_RIGHT);
+        assert_eq!(Quadrant::TOP_LEFT.opposite(), Quadrant::BOTTOM_RIGHT);
+    }
+
+    #[test]
+    fn opposite_of_half() {
+        assert_eq!((Quadrant::TOP_RIGHT | Quadrant::BOTTOM_RIGHT).opposite(), Quadrant::TOP_LEFT | Quadrant::BOTTOM_LEFT);
+        assert_eq!((Quadrant::TOP_LEFT | Quadrant::BOTTOM_LEFT).opposite(), Quadrant::TOP_RIGHT | Quadrant::BOTTOM_RIGHT);
+        assert_eq!((Quadrant::TOP_LEFT | Quadrant::TOP_RIGHT).opposite(), Quadrant::BOTTOM_LEFT | Quadrant::BOTTOM_RIGHT);
+        assert_eq!((Quadrant::BOTTOM_LEFT | Quadrant::BOTTOM_RIGHT).opposite(), Quadrant::TOP_LEFT | Quadrant::TOP_RIGHT);
+    }
+
+    #[test]
+    fn opposite_of_none_and_all() {
+        assert_eq!(Quadrant::empty().opposite(), Quadrant::empty());
+        assert_eq!(Quadrant::all().opposite(), Quadrant::all());
+    }
+
+    #[test]
+    fn opposite_points_away() {
+        let origin = Position::new(0, 0);
+        for enemy in [Position::new(4, 1), Position::new(-2, 7), Position::new(0, -3), Position::new(-5, 0)] {
+            let away = Position::from(origin.quadrant(enemy).opposite());
+            assert!(away.x * enemy.x <= 0 && away.y * enemy.y <= 0);
+        }
+    }
+
+    #[test]
+    fn negative_positions_are_off_the_map() {
+        let map = Map::new(4, 4);
+        assert!(Position::new(-1, 2).get_from_map(&map).is_none());
+        assert!(Position::new(2, -1).get_from_map(&map).is_none());
+        assert!(Position::new(4, 2).get_from_map(&map).is_none());
+        assert!(matches!(Position::new(1, 1).get_from_map(&map), Some(Tile::Ground { .. })));
+    }
+
+    #[test]
+    fn from_tiles_checks_the_size() {
+        let tiles = vec![Tile::default_ground(), Tile::Wall, Tile::default_ground()];
+        assert!(matches!(Map::from_tiles(2, 2, vec![Zone::Road], tiles.clone()), Err(MapError::SizeMismatch { tiles: 3, .. })));
+        assert!(matches!(Map::from_tiles(3, 1, Vec::new(), tiles.clone()), Err(MapError::UnknownZone(ZoneId::ROAD))));
+        let map = Map::from_tiles(3, 1, vec![Zone::Road], tiles).unwrap();
+        assert!(matches!(map.get(1, 0), Some(Tile::Wall)));
+    }
+
+    #[test]
+    fn resize_keeps_tiles_that_fit() {
+        let mut map = Map::new(6, 6);
+        map.set_tile(Position::new(2, 2), Tile::Obstacle { occupier: None });
+        map.resize(8, 5);
+        assert_eq!((map.get_size_x(), map.get_size_y()), (8, 5));
+        assert!(matches!(map.get(2, 2), Some(Tile::Obstacle { .. })));
+        assert!(map.is_breakable(&Position::new(2, 2)));
+        assert!(matches!(map.get(7, 2), Some(Tile::Wall)));
+        assert!(matches!(map.get(6, 2), Some(Tile::Ground { .. })));
+    }
+
+    #[test]
+    fn chunks_are_allocated_when_touched() {
+        let mut map = Map::new(64, 64);
+        // Only the chunks along the border hold walls.
+        assert_eq!(map.allocated_chunks(), 12);
+        let ground = map.ground(Zone::Home { owner: None });
+        map.set_tile(Position::new(20, 20), ground);
+        assert_eq!(map.allocated_chunks(), 13);
+        assert!(matches!(map.zone_at(&Position::new(20, 20)), Some(Zone::Home { .. })));
+        assert!(matches!(map.zone_at(&Position::new(30, 30)), Some(Zone::Road)));
+        assert_eq!(map.zone_id(Zone::Home { owner: None }), map.zone_id(Zone::Home { owner: None }));
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use bevy::prelude::{FromWorld, Res, ResMut, World};
+use serde::Deserialize;
+use crate::time::Clock;
+
+/// Something to be done towards a quest.
+#[derive(Deserialize, Clone)]
+pub enum Objective {
+    /// Come by an item of the name.
+    Obtain(String),
+    /// Hand an item of the name over to whoever is named.
+    Deliver {
+        item: String,
+        to: String,
+    },
+    TalkTo(String),
+    /// Live to see the dawn.
+    SurviveNight,
+    /// Unmask the werewolf.
+    FindWerewolf,
+}
+impl ToString for Objective {
+    fn to_string(&self) -> String {
+        match self {
+            Objective::Obtain(item) => format!("Get hold of: {item}"),
+            Objective::Deliver { item, to } => format!("Bring {item} to {to}"),
+            Objective::TalkTo(name) => format!("Talk to {name}"),
+            Objective::SurviveNight => "Survive the night".to_string(),
+            Objective::FindWerewolf => "Find the werewolf".to_string(),
+        }
+    }
+}
+
+/// What happened in the world that quests may care about.
+pub enum QuestEvent {
+    /// The player came by an item of the name.
+    Obtained(String),
+    /// The player handed an item of the name over.
+    Delivered {
+        item: String,
+        to: String,
+    },
+    TalkedTo(String),
+    /// The night is over.
+    Dawn,
+    /// Someone of the name died.
+    Died(String),
+    PlayerDied,
+    Unmasked,
+}
+
+#[derive(Deserialize)]
+struct QuestData {
+    title: String,
+    description: String,
+    objectives: Vec<Objective>,
+    /// Given at the start of the game rather than by someone.
+    #[serde(default)]
+    start: bool,
+}
+
+#[derive(Deserialize)]
+struct QuestsData {
+    quests: HashMap<String, QuestData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuestState {
+    /// Which objectives are done.
+    Active(Vec<bool>),
+    Completed,
+    Failed,
+}
+
+/// A quest the player was given.
+pub struct Quest {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub objectives: Vec<Objective>,
+    pub state: QuestState,
+}
+impl Quest {
+    /// Marks off objectives the event fulfills, failing the quest if it can no longer be done.
+    fn progress(&mut self, event: &QuestEvent) {
+        let done = match &mut self.state {
+            QuestState::Active(done) => done,
+            _ => return,
+        };
+        let mut failed = false;
+        for (objective, done) in self.objectives.iter().zip(done.iter_mut()) {
+            if *done { continue; }
+            match (objective, event) {
+                // Handing it over shows it was come by.
+                (Objective::Obtain(item), QuestEvent::Obtained(obtained) | QuestEvent::Delivered { item: obtained, .. }) => *done = item == obtained,
+                (Objective::Deliver { item, to }, QuestEvent::Delivered { item: delivered, to: receiver }) => {
+                    *done = item == delivered && to == receiver;
+                },
+                (Objective::TalkTo(name), QuestEvent::TalkedTo(talked_to)) => *done = name == talked_to,
+                (Objective::SurviveNight, QuestEvent::Dawn) |
+                (Objective::FindWerewolf, QuestEvent::Unmasked) => *done = true,
+                (Objective::Deliver { to: name, .. } | Objective::TalkTo(name), QuestEvent::Died(died)) => failed |= name == died,
+                (_, QuestEvent::PlayerDied) => failed = true,
+                _ => {},
+            }
+        }
+        let completed = done.iter().all(|done| *done);
+        if failed {
+            self.state = QuestState::Failed;
+        } else if completed {
+            self.state = QuestState::Completed;
+        }
+    }
+}
+
+/// Quests there are to be given, loaded from data, and those the player has.
+pub struct Quests {
+    definitions: HashMap<String, QuestData>,
+    /// In the order they were given.
+    given: Vec<Quest>,
+    was_night: bool,
+}
+impl Quests {
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        let data = ron::from_str::<QuestsData>(text)?;
+        let mut quests = Quests { definitions: data.quests, given: Vec::new(), was_night: false };
+        let mut starting = quests.definitions.iter()
+            .filter(|(_, quest)| quest.start)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        starting.sort();
+        for id in starting {
+            quests.give(&id);
+        }
+        Ok(quests)
+    }
+    pub fn given(&self) -> &Vec<Quest> {
+        &self.given
+    }
+    pub fn state(&self, id: &str) -> Option<&QuestState> {
+        self.given.iter().find(|quest| quest.id == id).map(|quest| &quest.state)
+    }
+    /// Hands the quest to the player, unless it is unknown or already given.
+    pub fn give(&mut self, id: &str) {
+        if self.state(id).is_some() { return; }
+        if let Some(quest) = self.definitions.get(id) {
+            self.given.push(Quest {
+                id: id.to_string(),
+                title: quest.title.clone(),
+                description: quest.description.clone(),
+                objectives: quest.objectives.clone(),
+                state: QuestState::Active(vec![false; quest.objectives.len()]),
+            });
+        }
+    }
+    /// Lets the quests given know what happened.
+    pub fn notify(&mut self, event: QuestEvent) {
+        for quest in self.given.iter_mut() {
+            quest.progress(&event);
+        }
+    }
+}
+impl FromWorld for Quests {
+    fn from_world(_world: &mut World) -> Self {
+        Quests::from_ron(include_str!("../data/quests.ron")).expect("valid quest data")
+    }
+}
+
+/// Lets quests know when the sun comes up.
+pub fn quest_update(mut quests: ResMut<Quests>, clock: Res<Clock>) {
+    let is_night = clock.is_night();
+    if quests.was_night && !is_night {
+        quests.notify(QuestEvent::Dawn);
+    }
+    quests.was_night = is_night;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUESTS: &str = r#"(
+        quests: {
+            "Night": (title: "", description: "", objectives: [SurviveNight], start: true),
+            "Errand": (title: "", description: "", objectives: [Obtain("Banana"), Deliver(item: "Banana", to: "Hilde")]),
+            "Word": (title: "", description: "", objectives: [TalkTo("Yseult")]),
+        },
+    )"#;
+
+    fn quests() -> Quests {
+        Quests::from_ron(QUESTS).unwrap()
+    }
+
+    fn delivered(item: &str, to: &str) -> QuestEvent {
+        QuestEvent::Delivered { item: item.to_string(), to: to.to_string() }
+    }
+
+    #[test]
+    fn starting_quests_are_given_on_load() {
+        let mut quests = quests();
+        assert_eq!(quests.state("Night"), Some(&QuestState::Active(vec![false])));
+        assert_eq!(quests.state("Errand"), None);
+        quests.notify(QuestEvent::Dawn);
+        assert_eq!(quests.state("Night"), Some(&QuestState::Completed));
+    }
+
+    #[test]
+    fn objectives_are_marked_off_until_complete() {
+        let mut quests = quests();
+        quests.give("Errand");
+        quests.notify(QuestEvent::Obtained("Apple".to_string()));
+        assert_eq!(quests.state("Errand"), Some(&QuestState::Active(vec![false, false])));
+        quests.notify(QuestEvent::Obtained("Banana".to_string()));
+        assert_eq!(quests.state("Errand"), Some(&QuestState::Active(vec![true, false])));
+        quests.notify(delivered("Banana", "Tamsin"));
+        assert_eq!(quests.state("Errand"), Some(&QuestState::Active(vec![true, false])));
+        quests.notify(delivered("Banana", "Hilde"));
+        assert_eq!(quests.state("Errand"), Some(&QuestState::Completed));
+    }
+
+    #[test]
+    fn delivering_shows_the_item_was_obtained() {
+        let mut quests = quests();
+        quests.give("Errand");
+        quests.notify(delivered("Banana", "Hilde"));
+        assert_eq!(quests.state("Errand"), Some(&QuestState::Completed));
+    }
+
+    #[test]
+    fn quests_fail_when_who_they_need_dies() {
+        let mut quests = quests();
+        quests.give("Errand");
+        quests.give("Word");
+        quests.notify(QuestEvent::Died("Hilde".to_string()));
+        assert_eq!(quests.state("Errand"), Some(&QuestState::Failed));
+        assert_eq!(quests.state("Word"), Some(&QuestState::Active(vec![false])));
+        // Failed quests stay failed.
+        quests.notify(delivered("Banana", "Hilde"));
+        assert_eq!(quests.state("Errand"), Some(&QuestState::Failed));
+
+        quests.notify(QuestEvent::PlayerDied);
+        assert_eq!(quests.state("Word"), Some(&QuestState::Failed));
+        assert_eq!(quests.state("Night"), Some(&QuestState::Failed));
+    }
+
+    #[test]
+    fn quests_are_given_once() {
+        let mut quests = quests();
+        quests.give("Word");
+        quests.notify(QuestEvent::TalkedTo("Yseult".to_string()));
+        quests.give("Word");
+        quests.give("Unknown");
+        assert_eq!(quests.given().iter().filter(|quest| quest.id == "Word").count(), 1);
+        assert_eq!(quests.state("Word"), Some(&QuestState::Completed));
+        assert_eq!(quests.state("Unknown"), None);
+    }
+}
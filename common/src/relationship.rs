@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use bevy::prelude::{Commands, Entity, Query, Res, ResMut, With, Without, World};
+use crate::{
+    physics::{Map, MapCache, Position},
+    character::{CharacterType, PlayerTag},
+    map_brain::{CharacterBehaviorData, HumanState, BehaviorData},
+    behaviors::{util::set_human_panic, pathfinder::{PathfinderBehavior, Follow, data::PathfinderGlobalData}},
+    time::Clock,
+    level::Dormant,
+    theft::Confront,
+};
+
+const MIN_SCORE: i32 = -100;
+const MAX_SCORE: i32 = 100;
+/// At or below, they want nothing to do with whoever it is,
+/// townsfolk run and the watch opens fire.
+pub const HOSTILE: i32 = -50;
+/// Below, they will not walk alongside whoever it is.
+pub const WARY: i32 = -20;
+
+/// How much each happening sways an opinion.
+pub const GIFT: i32 = 10;
+pub const TRADE: i32 = 2;
+pub const GREET: i32 = 1;
+pub const SHOT: i32 = -40;
+pub const SHOT_NEAR: i32 = -5;
+pub const THEFT: i32 = -30;
+pub const THEFT_WITNESSED: i32 = -10;
+
+/// Distance within which a shot upsets those around.
+pub const SHOT_NEAR_RADIUS: u32 = 6;
+
+/// What those of one kind think of another before they have met.
+fn attitude(of: &CharacterType, toward: &CharacterType) -> i32 {
+    match (of, toward) {
+        // The watch does not trust strangers.
+        (CharacterType::Rumdare, CharacterType::Player) => -5,
+        (_, CharacterType::Player) | (CharacterType::Player, _) => 0,
+        (CharacterType::Rumdare, CharacterType::Rumdare) => 20,
+        // The werewolf passes for one of the townsfolk.
+        _ => 10,
+    }
+}
+
+/// What characters think of each other, on top of how their kinds get along.
+#[derive(Default)]
+pub struct Relationships {
+    /// Keyed by who holds the opinion, then who it is of.
+    scores: HashMap<(Entity, Entity), i32>,
+}
+impl Relationships {
+    pub fn score(&self, of: (Entity, &CharacterType), toward: (Entity, &CharacterType)) -> i32 {
+        let personal = self.scores.get(&(of.0, toward.0)).copied().unwrap_or(0);
+        (attitude(of.1, toward.1) + personal).clamp(MIN_SCORE, MAX_SCORE)
+    }
+    pub fn change(&mut self, of: Entity, toward: Entity, amount: i32) {
+        if of == toward { return; }
+        let score = self.scores.entry((of, toward)).or_insert(0);
+        *score = (*score + amount).clamp(MIN_SCORE - MAX_SCORE, MAX_SCORE - MIN_SCORE);
+    }
+}
+
+/// Those who have turned on the player stop following them,
+/// townsfolk who cannot stand them run when they come close.
+pub fn relationship_update(
+    mut commands: Commands,
+    map: Res<Map>,
+    mut map_cache: ResMut<MapCache>,
+    clock: Res<Clock>,
+    relationships: Res<Relationships>,
+    pathfinder_data: Res<PathfinderGlobalData>,
+    player_query: Query<(Entity, &CharacterType, &Position), With<PlayerTag>>,
+    mut query: Query<(
+        Entity,
+        &CharacterType,
+        &Position,
+        &mut CharacterBehaviorData,
+        &mut BehaviorData<PathfinderBehavior>,
+        Option<&Follow>,
+        Option<&Confront>,
+    ), (Without<PlayerTag>, Without<Dormant>)>,
+) {
+    let (player, player_type, player_position) = match player_query.iter().next() {
+        Some(player) => player,
+        None => return,
+    };
+    for (entity, character_type, position, mut character_behavior_data, mut pathfinder, follow, confront) in query.iter_mut() {
+        if !map.is_active(position) { continue; }
+        let score = relationships.score((entity, character_type), (player, player_type));
+        // Going after a thief is not walking alongside them.
+        if score < WARY && confront.is_none() && follow.map_or(false, |Follow(leader)| *leader == player) {
+            commands.entity(entity).remove::<Follow>();
+        }
+        if score > HOSTILE || !matches!(character_type, CharacterType::Lerain) { continue; }
+        if let CharacterBehaviorData::Human { human_state: state @ (HumanState::Idle(_) | HumanState::Moving(_) | HumanState::Routine(_)) } = character_behavior_data.as_mut() {
+            if map.get_in_vision(&mut map_cache, *position, clock.vision_distance()).contains(player_position) {
+                set_human_panic(&pathfinder_data, &mut pathfinder.behavior, state, (character_type.clone(), *position), &[*player_position]);
+            }
+        }
+    }
+}
+
+/// Opinion the entity holds of the player, neutral if either is missing.
+pub fn opinion_of_player(world: &mut World, entity: Entity) -> i32 {
+    let player = world.query_filtered::<Entity, With<PlayerTag>>().iter(world).next();
+    match (player, world.get::<CharacterType>(entity)) {
+        (Some(player), Some(character_type)) => match world.get::<CharacterType>(player) {
+            Some(player_type) => world.resource::<Relationships>().score((entity, character_type), (player, player_type)),
+            None => 0,
+        },
+        _ => 0,
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use bevy::prelude::{Component, FromWorld, World};
+use serde::Deserialize;
+use crate::{physics::Position, time::is_hour_between};
+
+/// Place name standing in for the character's own home.
+const HOME_PLACE: &str = "Home";
+
+#[derive(Deserialize)]
+struct ScheduleEntryData {
+    from: u32,
+    to: u32,
+    place: String,
+}
+
+#[derive(Deserialize)]
+struct SchedulesData {
+    places: HashMap<String, Vec<(i32, i32)>>,
+    routines: HashMap<String, Vec<ScheduleEntryData>>,
+    #[serde(default)]
+    patrols: HashMap<String, Vec<(i32, i32)>>,
+}
+
+/// Places and routines townsfolk follow, loaded from data.
+pub struct Schedules {
+    places: HashMap<String, Vec<Position>>,
+    routines: HashMap<String, Vec<ScheduleEntryData>>,
+    patrols: HashMap<String, Vec<Position>>,
+}
+impl Schedules {
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        let data = ron::from_str::<SchedulesData>(text)?;
+        let to_positions = |points: HashMap<String, Vec<(i32, i32)>>| points.into_iter()
+            .map(|(name, points)| (name, points.into_iter().map(|(x, y)| Position::new(x, y)).collect()))
+            .collect();
+        Ok(Schedules {
+            places: to_positions(data.places),
+            routines: data.routines,
+            patrols: to_positions(data.patrols),
+        })
+    }
+    pub fn place(&self, name: &str) -> Option<&Vec<Position>> {
+        self.places.get(name)
+    }
+    /// Builds the routine of the given name for someone living at home.
+    pub fn routine(&self, name: &str, home: &str) -> Routine {
+        let mut entries = Vec::new();
+        if let Some(routine) = self.routines.get(name) {
+            for entry in routine.iter() {
+                let place = if entry.place == HOME_PLACE { home } else { &entry.place };
+                if let Some(places) = self.places.get(place) {
+                    entries.push(RoutineEntry {
+                        from: entry.from,
+                        to: entry.to,
+                        places: places.clone(),
+                    });
+                }
+            }
+        }
+        Routine { entries }
+    }
+    /// Patrol route of the given name, empty if there is none.
+    pub fn patrol(&self, name: &str) -> Patrol {
+        Patrol {
+            points: self.patrols.get(name).cloned().unwrap_or_default(),
+        }
+    }
+}
+impl FromWorld for Schedules {
+    fn from_world(_world: &mut World) -> Self {
+        Schedules::from_ron(include_str!("../data/schedules.ron")).expect("valid schedules data")
+    }
+}
+
+pub struct RoutineEntry {
+    from: u32,
+    to: u32,
+    pub places: Vec<Position>,
+}
+
+/// Where a character wants to be throughout the day.
+#[derive(Component, Default)]
+pub struct Routine {
+    entries: Vec<RoutineEntry>,
+}
+impl Routine {
+    /// Index and entry scheduled at the hour, none if free to wander.
+    pub fn at(&self, hour: u32) -> Option<(usize, &RoutineEntry)> {
+        self.entries.iter()
+            .enumerate()
+            .find(|(_, entry)| is_hour_between(hour, entry.from, entry.to))
+    }
+}
+
+/// Points a guard walks between, in order and looping back.
+#[derive(Component, Default)]
+pub struct Patrol {
+    points: Vec<Position>,
+}
+impl Patrol {
+    pub fn get(&self, index: usize) -> Option<Position> {
+        self.points.get(index).cloned()
+    }
+    pub fn next(&self, index: usize) -> usize {
+        if self.points.is_empty() { 0 } else { (index + 1) % self.points.len() }
+    }
+    /// Index of the point closest to the position.
+    pub fn nearest(&self, position: &Position) -> usize {
+        self.points.iter()
+            .enumerate()
+            .min_by_key(|(_, p)| p.distance(position))
+            .map_or(0, |(i, _)| i)
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use bevy::prelude::{Changed, Entity, Or, Query, RemovedComponents, Res, ResMut};
+use crate::{
+    physics::{Map, Position},
+    character::{CharacterType, Interact, InteractData},
+    inventory::FloorItem,
+    chunks::CHUNK_SIZE,
+    level::LevelId,
+};
+
+/// Side of the square cells entities are bucketed in.
+const CELL_SIZE: i32 = CHUNK_SIZE as i32;
+
+/// What kind of thing is on a tile, any number of each can share it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Layer {
+    /// Items lying about.
+    Floor,
+    /// Chests, doors, stairs and whatever else stays put.
+    Fixture,
+    Character,
+    /// Projectiles in flight.
+    Effect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Located {
+    pub entity: Entity,
+    pub position: Position,
+    pub layer: Layer,
+}
+
+/// Where every entity with a position is, kept apart from the tile occupiers
+/// so several things can be found on the same tile.
+/// Lookups are on the level of the map last synced with.
+#[derive(Default)]
+pub struct SpatialIndex {
+    level: LevelId,
+    cells: HashMap<(LevelId, i32, i32), Vec<Located>>,
+    entities: HashMap<Entity, (LevelId, Position)>,
+}
+impl SpatialIndex {
+    fn cell(level: LevelId, position: &Position) -> (LevelId, i32, i32) {
+        (level, position.x.div_euclid(CELL_SIZE), position.y.div_euclid(CELL_SIZE))
+    }
+    /// Puts the entity at the position, moving it if already indexed.
+    pub fn insert(&mut self, entity: Entity, level: LevelId, position: Position, layer: Layer) {
+        self.remove(entity);
+        self.cells.entry(Self::cell(level, &position)).or_default().push(Located { entity, position, layer });
+        self.entities.insert(entity, (level, position));
+    }
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some((level, position)) = self.entities.remove(&entity) {
+            let cell = Self::cell(level, &position);
+            if let Some(located) = self.cells.get_mut(&cell) {
+                located.retain(|l| l.entity != entity);
+                if located.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+    pub fn position_of(&self, entity: Entity) -> Option<Position> {
+        self.entities.get(&entity).map(|(_, position)| *position)
+    }
+    /// Everything on the tile.
+    pub fn at(&self, position: &Position) -> impl Iterator<Item = &Located> + '_ {
+        let position = *position;
+        self.cells.get(&Self::cell(self.level, &position))
+            .into_iter()
+            .flatten()
+            .filter(move |located| located.position == position)
+    }
+    /// Everything on the tile in the layer.
+    pub fn at_layer(&self, position: &Position, layer: Layer) -> impl Iterator<Item = Entity> + '_ {
+        self.at(position).filter(move |located| located.layer == layer).map(|located| located.entity)
+    }
+    /// Everything within the corners given.
+    pub fn in_rect(&self, bottom_left: Position, top_right: Position) -> impl Iterator<Item = &Located> + '_ {
+        let (level, min_x, min_y) = Self::cell(self.level, &bottom_left);
+        let (_, max_x, max_y) = Self::cell(self.level, &top_right);
+        (min_y..=max_y)
+            .flat_map(move |y| (min_x..=max_x).map(move |x| (level, x, y)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .filter(move |located| {
+                (bottom_left.x..=top_right.x).contains(&located.position.x)
+                    && (bottom_left.y..=top_right.y).contains(&located.position.y)
+            })
+    }
+    /// Everything closer to the center than the radius.
+    pub fn in_radius(&self, center: Position, radius: u32) -> impl Iterator<Item = &Located> + '_ {
+        let offset = Position::new(radius as i32, radius as i32);
+        self.in_rect(center - offset, center + offset)
+            .filter(move |located| located.position.distance(&center) < radius * radius)
+    }
+}
+
+fn layer_of(character_type: Option<&CharacterType>, interact: Option<&Interact>, floor_item: Option<&FloorItem>) -> Layer {
+    match (character_type, interact.map(|interact| &interact.data), floor_item) {
+        (Some(_), _, _) => Layer::Character,
+        (None, Some(InteractData::Projectile { .. }), _) => Layer::Effect,
+        (None, _, Some(_)) => Layer::Floor,
+        _ => Layer::Fixture,
+    }
+}
+
+/// Follows entities as they spawn, move and change level.
+pub fn spatial_index_update(
+    map: Res<Map>,
+    mut index: ResMut<SpatialIndex>,
+    query: Query<(Entity, &Position, &LevelId, Option<&CharacterType>, Option<&Interact>, Option<&FloorItem>), Or<(Changed<Position>, Changed<LevelId>)>>,
+) {
+    index.level = map.level();
+    for (entity, position, level, character_type, interact, floor_item) in query.iter() {
+        index.insert(entity, *level, *position, layer_of(character_type, interact, floor_item));
+    }
+}
+
+/// Forgets entities as they despawn.
+/// Runs after the update stage has applied its commands, removals are cleared at the end of the frame.
+pub fn spatial_index_removal_update(mut index: ResMut<SpatialIndex>, removed: RemovedComponents<Position>) {
+    for entity in removed.iter() {
+        index.remove(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use super::*;
+    use crate::testing;
+
+    const OTHER: LevelId = LevelId(1);
+
+    fn entities<'a>(located: impl Iterator<Item = &'a Located>) -> Vec<Entity> {
+        let mut entities = located.map(|located| located.entity).collect::<Vec<_>>();
+        entities.sort();
+        entities
+    }
+
+    #[test]
+    fn insert_moves_between_cells() {
+        let mut index = SpatialIndex::default();
+        let entity = Entity::from_raw(0);
+        index.insert(entity, LevelId::SURFACE, Position::new(CELL_SIZE - 1, 0), Layer::Character);
+        assert_eq!(entities(index.at(&Position::new(CELL_SIZE - 1, 0))), vec![entity]);
+
+        index.insert(entity, LevelId::SURFACE, Position::new(CELL_SIZE, 0), Layer::Character);
+        assert!(index.at(&Position::new(CELL_SIZE - 1, 0)).next().is_none());
+        assert_eq!(entities(index.at(&Position::new(CELL_SIZE, 0))), vec![entity]);
+        assert_eq!(index.position_of(entity), Some(Position::new(CELL_SIZE, 0)));
+        // The cell left behind is dropped.
+        assert_eq!(index.cells.len(), 1);
+    }
+
+    #[test]
+    fn remove_forgets_the_entity() {
+        let mut index = SpatialIndex::default();
+        let entity = Entity::from_raw(0);
+        let other = Entity::from_raw(1);
+        index.insert(entity, LevelId::SURFACE, Position::new(3, 3), Layer::Floor);
+        index.insert(other, LevelId::SURFACE, Position::new(3, 3), Layer::Character);
+        assert_eq!(index.at_layer(&Position::new(3, 3), Layer::Floor).collect::<Vec<_>>(), vec![entity]);
+
+        index.remove(entity);
+        assert_eq!(index.position_of(entity), None);
+        assert_eq!(entities(index.at(&Position::new(3, 3))), vec![other]);
+        index.remove(other);
+        assert!(index.cells.is_empty());
+        // Nothing to remove.
+        index.remove(other);
+    }
+
+    #[test]
+    fn negative_positions_fall_in_their_own_cells() {
+        assert_eq!(SpatialIndex::cell(LevelId::SURFACE, &Position::new(-1, -1)), (LevelId::SURFACE, -1, -1));
+        assert_eq!(SpatialIndex::cell(LevelId::SURFACE, &Position::new(0, 0)), (LevelId::SURFACE, 0, 0));
+        assert_eq!(SpatialIndex::cell(LevelId::SURFACE, &Position::new(-CELL_SIZE, 0)), (LevelId::SURFACE, -1, 0));
+        assert_eq!(SpatialIndex::cell(LevelId::SURFACE, &Position::new(-CELL_SIZE - 1, 0)), (LevelId::SURFACE, -2, 0));
+    }
+
+    #[test]
+    fn queries_span_cell_boundaries() {
+        let mut index = SpatialIndex::default();
+        let positions = [
+            Position::new(-1, -1),
+            Position::new(0, 0),
+            Position::new(2, 2),
+            Position::new(-2, -2),
+            Position::new(3, 0),
+            Position::new(-3, 0),
+            Position::new(CELL_SIZE, CELL_SIZE),
+        ];
+        for (i, position) in positions.iter().enumerate() {
+            index.insert(Entity::from_raw(i as u32), LevelId::SURFACE, *position, Layer::Character);
+        }
+        let ids = |ids: &[u32]| ids.iter().map(|id| Entity::from_raw(*id)).collect::<Vec<_>>();
+
+        // Strictly closer than the radius.
+        assert_eq!(entities(index.in_radius(Position::new(0, 0), 3)), ids(&[0, 1, 2, 3]));
+        assert_eq!(entities(index.in_radius(Position::new(0, 0), 4)), ids(&[0, 1, 2, 3, 4, 5]));
+        assert_eq!(entities(index.in_rect(Position::new(-3, -1), Position::new(0, 0))), ids(&[0, 1, 5]));
+        assert_eq!(entities(index.in_rect(Position::new(1, 1), Position::new(CELL_SIZE, CELL_SIZE))), ids(&[2, 6]));
+    }
+
+    #[test]
+    fn changing_level_reinserts() {
+        let mut index = SpatialIndex::default();
+        let entity = Entity::from_raw(0);
+        let position = Position::new(5, 5);
+        index.insert(entity, LevelId::SURFACE, position, Layer::Character);
+        index.insert(entity, OTHER, position, Layer::Character);
+        assert!(index.at(&position).next().is_none());
+        assert!(index.in_radius(position, 2).next().is_none());
+
+        index.level = OTHER;
+        assert_eq!(entities(index.at(&position)), vec![entity]);
+        assert_eq!(entities(index.in_radius(position, 2)), vec![entity]);
+        assert_eq!(index.entities.len(), 1);
+        assert_eq!(index.cells.len(), 1);
+    }
+
+    #[derive(Component)]
+    struct Doomed;
+
+    fn despawn_doomed(mut commands: Commands, query: Query<Entity, With<Doomed>>) {
+        for entity in query.iter() {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    #[test]
+    fn despawned_entities_are_forgotten() {
+        let mut app = testing::app(Map::new(10, 10));
+        // Despawned later in the update stage, as dead characters and spent projectiles are.
+        app.add_system(spatial_index_update.label("spatial"))
+            .add_system(despawn_doomed.after("spatial"))
+            .add_system_to_stage(CoreStage::PostUpdate, spatial_index_removal_update);
+        let kept = app.world.spawn().insert(Position::new(1, 1)).insert(LevelId::SURFACE).id();
+        let doomed = app.world.spawn().insert(Position::new(2, 2)).insert(LevelId::SURFACE).insert(Doomed).id();
+
+        app.update();
+        let index = app.world.resource::<SpatialIndex>();
+        assert_eq!(index.position_of(kept), Some(Position::new(1, 1)));
+        assert_eq!(index.position_of(doomed), None);
+        assert!(index.at(&Position::new(2, 2)).next().is_none());
+
+        app.world.entity_mut(kept).insert(Doomed);
+        app.update();
+        app.update();
+        let index = app.world.resource::<SpatialIndex>();
+        assert!(index.entities.is_empty());
+        assert!(index.cells.is_empty());
+    }
+}
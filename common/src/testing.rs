@@ -0,0 +1,80 @@
+use bevy::{ecs::system::CommandQueue, prelude::*};
+use crate::{
+    ActionInput, PlayerState, Settings,
+    physics::{Map, MapCache, Position},
+    character::{CharacterData, CharacterType, Health, PlayerTag, Sprite},
+    inventory::Inventory,
+    behaviors::{
+        pathfinder::{PathfinderBudget, rooms::RoomGraph, reservations::Reservations, danger::DangerZones},
+        rumdare::RumdareAlert,
+    },
+    dialogue::{Dialogue, DialogueTrees},
+    interaction::Interactions,
+    journal::Journal,
+    level::Levels,
+    loot_menu::LootMenu,
+    noise::Noise,
+    quest::Quests,
+    relationship::Relationships,
+    spatial::SpatialIndex,
+    theft::Thefts,
+    time::Clock,
+};
+
+/// An app playing on the map, with every resource the game systems look for.
+/// Systems are added by the test, in the order the game runs them.
+pub fn app(map: Map) -> App {
+    let mut app = App::new();
+    app.insert_resource(map)
+        .insert_resource(MapCache::default())
+        .insert_resource(PlayerState::default())
+        .insert_resource(Settings::default())
+        .insert_resource(ActionInput::None)
+        .insert_resource(Dialogue::default())
+        .init_resource::<DialogueTrees>()
+        .insert_resource(LootMenu::default())
+        .insert_resource(PathfinderBudget::default())
+        .insert_resource(RoomGraph::default())
+        .insert_resource(Reservations::default())
+        .insert_resource(DangerZones::default())
+        .insert_resource(Noise::default())
+        .insert_resource(Clock::default())
+        .insert_resource(Journal::default())
+        .init_resource::<Quests>()
+        .insert_resource(RumdareAlert::default())
+        .init_resource::<Levels>()
+        .init_resource::<SpatialIndex>()
+        .init_resource::<Interactions>()
+        .init_resource::<Relationships>()
+        .init_resource::<Thefts>();
+    app
+}
+
+/// Runs the spawning against the map of the world, as setup does.
+pub fn spawn(world: &mut World, spawning: impl FnOnce(&mut Commands, &mut Map)) {
+    let mut queue = CommandQueue::default();
+    world.resource_scope(|world, mut map: Mut<Map>| {
+        let mut commands = Commands::new(&mut queue, world);
+        spawning(&mut commands, &mut map);
+    });
+    queue.apply(world);
+}
+
+/// The character of the name.
+pub fn named(world: &mut World, name: &str) -> Entity {
+    world.query::<(Entity, &Name)>().iter(world)
+        .find(|(_, n)| n.as_str() == name)
+        .map(|(entity, _)| entity)
+        .unwrap_or_else(|| panic!("no one named {name}"))
+}
+
+/// The player, carrying the inventory.
+pub fn spawn_player(commands: &mut Commands, map: &mut Map, position: Position, inventory: Inventory) {
+    map.spawn_character(commands, Sprite::Player, position, Health::new(4), CharacterType::Player, CharacterData::Human, |mut entity_commands| {
+        entity_commands.insert(PlayerTag).insert(inventory);
+    });
+}
+
+pub fn player(world: &mut World) -> Entity {
+    world.query_filtered::<Entity, With<PlayerTag>>().single(world)
+}
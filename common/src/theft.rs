@@ -0,0 +1,242 @@
+use bevy::prelude::{Component, Entity, Mut, Name, With, Without, World};
+use crate::{
+    physics::{Map, MapCache, Position},
+    character::{CharacterData, WereForm, PlayerTag},
+    inventory::{Inventory, ItemId},
+    level::Dormant,
+    behaviors::pathfinder::Follow,
+    relationship::{Relationships, THEFT, THEFT_WITNESSED},
+    dialogue::{DialogueOption, Effect},
+    time::Clock,
+};
+
+/// Whose a container is, by name.
+#[derive(Component)]
+pub struct Owner(pub String);
+
+/// Saw the thief at it and is on the way to have a word.
+#[derive(Component)]
+pub struct Confront(pub Entity);
+
+struct Stolen {
+    thief: Entity,
+    item: ItemId,
+    container: Entity,
+    owner: Option<Entity>,
+}
+
+/// What thieves were seen taking, until it is handed back.
+#[derive(Default)]
+pub struct Thefts {
+    stolen: Vec<Stolen>,
+}
+impl Thefts {
+    /// Is anything the thief was seen taking still unreturned?
+    pub fn owes(&self, thief: Entity) -> bool {
+        self.stolen.iter().any(|stolen| stolen.thief == thief)
+    }
+}
+
+/// Characters who can see the position, beasts do not care who takes what.
+fn witnesses(world: &mut World, position: Position) -> Vec<(Entity, Position)> {
+    let distance = world.resource::<Clock>().vision_distance();
+    let characters = world.query_filtered::<(Entity, &Position, &CharacterData), (With<Name>, Without<PlayerTag>, Without<Dormant>)>()
+        .iter(world)
+        .filter(|(_, _, character_data)| !matches!(character_data, CharacterData::Werewolf { form: WereForm::Beast }))
+        .map(|(entity, position, _)| (entity, *position))
+        .collect::<Vec<_>>();
+    world.resource_scope(|world, mut map_cache: Mut<MapCache>| {
+        let map = world.resource::<Map>();
+        characters.into_iter()
+            .filter(|(_, p)| map.get_in_vision(&mut map_cache, *p, distance).contains(&position))
+            .collect()
+    })
+}
+
+/// Taking from a container that belongs to someone is theft if anyone sees it.
+/// The owner hears of it, everyone who saw thinks less of the thief
+/// and whoever was closest comes over to have a word.
+pub fn check_theft(world: &mut World, container: Entity, thief: Entity, item: ItemId) {
+    let owner = match world.get::<Owner>(container) {
+        Some(Owner(owner)) => owner.clone(),
+        None => return,
+    };
+    let position = match world.get::<Position>(thief) {
+        Some(position) => *position,
+        None => return,
+    };
+    let seen_by = witnesses(world, position);
+    if seen_by.is_empty() { return; }
+    let owner = world.query::<(Entity, &Name)>().iter(world)
+        .find(|(_, name)| name.as_str() == owner)
+        .map(|(entity, _)| entity);
+    let mut relationships = world.resource_mut::<Relationships>();
+    if let Some(owner) = owner {
+        relationships.change(owner, thief, THEFT);
+    }
+    for (witness, _) in seen_by.iter().filter(|(witness, _)| Some(*witness) != owner) {
+        relationships.change(*witness, thief, THEFT_WITNESSED);
+    }
+    world.resource_mut::<Thefts>().stolen.push(Stolen { thief, item, container, owner });
+    if let Some((closest, _)) = seen_by.iter().min_by_key(|(_, p)| p.distance(&position)) {
+        world.entity_mut(*closest).insert(Confront(thief)).insert(Follow(thief));
+    }
+}
+
+/// What they say once they catch up with the thief.
+pub fn confrontation(name: &str) -> (String, Vec<(String, DialogueOption)>) {
+    (
+        format!("{name}: I saw you help yourself to what is not yours. Put it back."),
+        vec![
+            ("Give it back.".to_string(), DialogueOption::Branch { effects: vec![Effect::ReturnStolen], next: None }),
+            ("Make me.".to_string(), DialogueOption::Branch { effects: vec![Effect::Opinion(THEFT_WITNESSED), Effect::EndConfrontation], next: None }),
+        ],
+    )
+}
+
+/// Stops confronting whoever it was after.
+pub fn end_confrontation(world: &mut World, entity: Entity) {
+    let mut entity = world.entity_mut(entity);
+    entity.remove::<Confront>();
+    entity.remove::<Follow>();
+}
+
+/// Puts what the thief still carries back where it was taken from and the owners forgive half of it.
+/// Nobody confronts them any more once they have handed back everything they were seen taking.
+pub fn return_stolen(world: &mut World, thief: Entity) {
+    let (theirs, others) = std::mem::take(&mut world.resource_mut::<Thefts>().stolen)
+        .into_iter()
+        .partition::<Vec<_>, _>(|stolen| stolen.thief == thief);
+    world.resource_mut::<Thefts>().stolen = others;
+    for stolen in theirs {
+        let item = match world.get::<Inventory>(stolen.container) {
+            Some(_) => world.get_mut::<Inventory>(thief).and_then(|mut inventory| {
+                let index = inventory.items().iter().position(|item| item.id() == stolen.item)?;
+                Some(inventory.remove_item(index))
+            }),
+            None => None,
+        };
+        match (item, world.get_mut::<Inventory>(stolen.container)) {
+            (Some(item), Some(mut inventory)) => inventory.add_item(item),
+            _ => {
+                // Eaten, handed on or nowhere to put it, still owed.
+                world.resource_mut::<Thefts>().stolen.push(stolen);
+                continue;
+            },
+        }
+        if let Some(owner) = stolen.owner {
+            world.resource_mut::<Relationships>().change(owner, thief, -THEFT / 2);
+        }
+    }
+    if world.resource::<Thefts>().owes(thief) { return; }
+    let confronting = world.query::<(Entity, &Confront)>().iter(world)
+        .filter(|(_, Confront(target))| *target == thief)
+        .map(|(entity, _)| entity)
+        .collect::<Vec<_>>();
+    for entity in confronting {
+        end_confrontation(world, entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use super::*;
+    use crate::{
+        physics::{Tile, Zone},
+        character::{CharacterType, LootableTag},
+        loot_menu::transfer_item,
+        schedule::Routine,
+        testing::{self, named, player},
+        inventory::Item,
+        util::{spawn_chest, spawn_lerain},
+    };
+
+    fn score(world: &World, of: Entity, player: Entity) -> i32 {
+        world.resource::<Relationships>().score((of, &CharacterType::Lerain), (player, &CharacterType::Player))
+    }
+
+    /// A chest with an apple by the player, a wall down the middle hiding the far side.
+    fn app(home: Option<&str>) -> App {
+        let mut map = Map::new(30, 30);
+        if let Some(owner) = home {
+            let ground = map.ground(Zone::home(owner));
+            map.set_tile(Position::new(6, 5), ground);
+        }
+        for y in 0..30 {
+            map.set_tile(Position::new(15, y), Tile::Wall);
+        }
+        let mut app = testing::app(map);
+        testing::spawn(&mut app.world, |commands, map| {
+            spawn_chest(commands, map, Position::new(6, 5), Inventory::new(vec![Box::new(Item::new_apple())]));
+            spawn_lerain(commands, map, "Away", Position::new(20, 5), Routine::default(), Vec::new());
+            testing::spawn_player(commands, map, Position::new(6, 6), Inventory::new(vec![Box::new(Item::new_apple())]));
+        });
+        app
+    }
+
+    fn chest(world: &mut World) -> Entity {
+        world.query_filtered::<Entity, With<LootableTag>>().single(world)
+    }
+
+    #[test]
+    fn seen_theft_is_remembered_and_confronted() {
+        let mut app = app(Some("Owner"));
+        testing::spawn(&mut app.world, |commands, map| {
+            spawn_lerain(commands, map, "Owner", Position::new(9, 9), Routine::default(), Vec::new());
+            spawn_lerain(commands, map, "Bystander", Position::new(2, 3), Routine::default(), Vec::new());
+        });
+        let owner = named(&mut app.world, "Owner");
+        let bystander = named(&mut app.world, "Bystander");
+        let away = named(&mut app.world, "Away");
+        let player = player(&mut app.world);
+        let chest = chest(&mut app.world);
+        let id = app.world.get::<Inventory>(chest).unwrap().items()[0].id();
+
+        transfer_item(&mut app, (chest, 0), player);
+        let world = &mut app.world;
+        assert_eq!(score(world, owner, player), THEFT);
+        assert_eq!(score(world, bystander, player), THEFT_WITNESSED);
+        assert_eq!(score(world, away, player), 0);
+        // The owner stands closest to the player.
+        assert!(world.get::<Confront>(owner).is_some());
+        assert!(world.get::<Confront>(bystander).is_none());
+        assert!(world.resource::<Thefts>().owes(player));
+
+        // Only the apple taken goes back, not the one the player already had.
+        return_stolen(world, player);
+        assert!(world.get::<Inventory>(chest).unwrap().items().iter().any(|item| item.id() == id));
+        assert!(!world.get::<Inventory>(player).unwrap().items().iter().any(|item| item.id() == id));
+        assert_eq!(world.get::<Inventory>(player).unwrap().items().len(), 1);
+        assert_eq!(score(world, owner, player), THEFT - THEFT / 2);
+        assert!(world.get::<Confront>(owner).is_none());
+        assert!(!world.resource::<Thefts>().owes(player));
+    }
+
+    #[test]
+    fn unowned_taking_is_not_theft() {
+        let mut app = app(None);
+        testing::spawn(&mut app.world, |commands, map| {
+            spawn_lerain(commands, map, "Near", Position::new(9, 9), Routine::default(), Vec::new());
+        });
+        let near = named(&mut app.world, "Near");
+        let player = player(&mut app.world);
+        let chest = chest(&mut app.world);
+        transfer_item(&mut app, (chest, 0), player);
+        assert!(!app.world.resource::<Thefts>().owes(player));
+        assert_eq!(score(&app.world, near, player), 0);
+    }
+
+    #[test]
+    fn unseen_taking_is_not_theft() {
+        let mut app = app(Some("Away"));
+        let away = named(&mut app.world, "Away");
+        let player = player(&mut app.world);
+        let chest = chest(&mut app.world);
+        // The only one around cannot see past the wall.
+        transfer_item(&mut app, (chest, 0), player);
+        assert!(!app.world.resource::<Thefts>().owes(player));
+        assert_eq!(score(&app.world, away, player), 0);
+        assert!(app.world.get::<Confront>(away).is_none());
+    }
+}
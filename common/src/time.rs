@@ -0,0 +1,125 @@
+use bevy::prelude::ResMut;
+
+pub const TICKS_PER_HOUR: u64 = 20;
+pub const HOURS_PER_DAY: u64 = 24;
+/// Hour the game starts at.
+const START_HOUR: u64 = 8;
+/// Night lasts from dusk until dawn.
+const DUSK_HOUR: u32 = 20;
+const DAWN_HOUR: u32 = 6;
+/// Phase of the moon on the first day, counted from the new moon.
+const START_MOON_PHASE: u64 = 2;
+pub const DAY_VISION_DISTANCE: u32 = 20;
+/// Vision on a moonless night, brighter moons add to it.
+const NIGHT_VISION_DISTANCE: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoonPhase {
+    New,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    Full,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+impl MoonPhase {
+    const CYCLE: [MoonPhase; 8] = [
+        MoonPhase::New,
+        MoonPhase::WaxingCrescent,
+        MoonPhase::FirstQuarter,
+        MoonPhase::WaxingGibbous,
+        MoonPhase::Full,
+        MoonPhase::WaningGibbous,
+        MoonPhase::LastQuarter,
+        MoonPhase::WaningCrescent,
+    ];
+    pub fn from_day(day: u64) -> MoonPhase {
+        let cycle = Self::CYCLE.len() as u64;
+        Self::CYCLE[((day + START_MOON_PHASE) % cycle) as usize]
+    }
+    /// How lit the night is, from 0 at new moon to 4 at full moon.
+    pub const fn brightness(&self) -> u32 {
+        match self {
+            MoonPhase::New => 0,
+            MoonPhase::WaxingCrescent | MoonPhase::WaningCrescent => 1,
+            MoonPhase::FirstQuarter | MoonPhase::LastQuarter => 2,
+            MoonPhase::WaxingGibbous | MoonPhase::WaningGibbous => 3,
+            MoonPhase::Full => 4,
+        }
+    }
+    pub const fn name(&self) -> &'static str {
+        match self {
+            MoonPhase::New => "New Moon",
+            MoonPhase::WaxingCrescent => "Waxing Crescent",
+            MoonPhase::FirstQuarter => "First Quarter",
+            MoonPhase::WaxingGibbous => "Waxing Gibbous",
+            MoonPhase::Full => "Full Moon",
+            MoonPhase::WaningGibbous => "Waning Gibbous",
+            MoonPhase::LastQuarter => "Last Quarter",
+            MoonPhase::WaningCrescent => "Waning Crescent",
+        }
+    }
+}
+
+pub struct Clock {
+    tick: u64,
+}
+impl Default for Clock {
+    fn default() -> Self {
+        Clock { tick: START_HOUR * TICKS_PER_HOUR }
+    }
+}
+impl Clock {
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+    pub fn hour(&self) -> u32 {
+        ((self.tick / TICKS_PER_HOUR) % HOURS_PER_DAY) as u32
+    }
+    pub fn minute(&self) -> u32 {
+        ((self.tick % TICKS_PER_HOUR) * 60 / TICKS_PER_HOUR) as u32
+    }
+    pub fn day(&self) -> u64 {
+        self.tick / (TICKS_PER_HOUR * HOURS_PER_DAY)
+    }
+    pub fn is_night(&self) -> bool {
+        is_hour_between(self.hour(), DUSK_HOUR, DAWN_HOUR)
+    }
+    /// Day the current night began on, hours past midnight belong to the day before.
+    pub fn night(&self) -> u64 {
+        if self.hour() < DAWN_HOUR {
+            self.day().saturating_sub(1)
+        } else {
+            self.day()
+        }
+    }
+    pub fn moon_phase(&self) -> MoonPhase {
+        MoonPhase::from_day(self.night())
+    }
+    /// True if the werewolf may take its beast form.
+    pub fn is_beast_night(&self) -> bool {
+        self.is_night() && self.moon_phase() != MoonPhase::New
+    }
+    pub fn vision_distance(&self) -> u32 {
+        if self.is_night() {
+            NIGHT_VISION_DISTANCE + self.moon_phase().brightness() * 2
+        } else {
+            DAY_VISION_DISTANCE
+        }
+    }
+}
+
+/// True if the hour is within [from, to), wrapping past midnight.
+pub fn is_hour_between(hour: u32, from: u32, to: u32) -> bool {
+    if from <= to {
+        hour >= from && hour < to
+    } else {
+        hour >= from || hour < to
+    }
+}
+
+pub fn clock_update(mut clock: ResMut<Clock>) {
+    clock.tick += 1;
+}
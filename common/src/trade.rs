@@ -0,0 +1,232 @@
+use std::collections::BTreeSet;
+use bevy::prelude::{App, Entity, With};
+use crate::{
+    character::PlayerTag,
+    inventory::{Inventory, Equipment, Item},
+    loot_menu::{LootMenu, exchange_items},
+    relationship::{Relationships, HOSTILE, TRADE, opinion_of_player},
+};
+
+/// How much more something is worth to whoever needs it.
+const NEED_FACTOR: u32 = 2;
+
+/// Items marked on either side of a barter.
+#[derive(Default)]
+pub struct Trade {
+    /// Indices into the inventory of the player.
+    pub offered: BTreeSet<usize>,
+    /// Indices into the inventory of the trader.
+    pub asked: BTreeSet<usize>,
+    /// What came of the last offer.
+    pub outcome: Option<Result<(), TradeError>>,
+}
+
+/// Why an offer fell through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeError {
+    NothingAsked,
+    /// They think it is not worth it.
+    Refused,
+    /// They will not deal with the player at all.
+    Unwelcome,
+    /// Marked items are no longer there.
+    Unavailable,
+}
+impl std::fmt::Display for TradeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TradeError::NothingAsked => write!(f, "Nothing asked for"),
+            TradeError::Refused => write!(f, "Refused"),
+            TradeError::Unwelcome => write!(f, "They will not deal with you"),
+            TradeError::Unavailable => write!(f, "Items are gone"),
+        }
+    }
+}
+
+/// What the trader has and holds, which decides what they are short of,
+/// and what they think of the player, which decides how much they ask.
+pub struct Trader<'a> {
+    pub inventory: &'a Inventory,
+    pub equipment: Option<&'a Equipment>,
+    pub opinion: i32,
+}
+impl<'a> Trader<'a> {
+    /// Items of the same kind they hold, the one in hand included.
+    fn held(&self, item: &Item) -> usize {
+        let kind = std::mem::discriminant(item);
+        self.inventory.items().iter()
+            .chain(self.equipment.and_then(|equipment| equipment.equipped.as_ref()))
+            .filter(|held| std::mem::discriminant(held.as_ref()) == kind)
+            .count()
+    }
+    /// Worth of the item to them, more if they have none of its kind
+    /// or it is the last one they would part with.
+    /// Those fond of the player part with things for less, those who are not for more.
+    pub fn worth(&self, item: &Item, parting: bool) -> u32 {
+        let held = self.held(item);
+        let needed = if parting { held <= 1 } else { held == 0 };
+        let worth = if needed { item.value() * NEED_FACTOR } else { item.value() };
+        if parting {
+            // From half price for a close friend to half again for a sworn enemy.
+            let percent = (100 - self.opinion / 2) as u32;
+            (worth * percent + 99) / 100
+        } else {
+            worth
+        }
+    }
+}
+
+impl Trade {
+    /// Marks the item or takes the mark off again.
+    pub fn toggle(&mut self, ours: bool, index: usize) {
+        let marked = if ours { &mut self.offered } else { &mut self.asked };
+        if !marked.remove(&index) {
+            marked.insert(index);
+        }
+        self.outcome = None;
+    }
+    /// Worth of everything offered to the trader.
+    pub fn offer_worth(&self, player: &Inventory, trader: &Trader) -> u32 {
+        self.offered.iter()
+            .filter_map(|index| player.items().get(*index))
+            .map(|item| trader.worth(item, false))
+            .sum()
+    }
+    /// Worth of everything asked for to the trader.
+    pub fn asked_worth(&self, trader: &Trader) -> u32 {
+        self.asked.iter()
+            .filter_map(|index| trader.inventory.items().get(*index))
+            .map(|item| trader.worth(item, true))
+            .sum()
+    }
+    fn evaluate(&self, player: &Inventory, trader: &Trader) -> Result<(), TradeError> {
+        if trader.opinion <= HOSTILE {
+            return Err(TradeError::Unwelcome);
+        }
+        if self.asked.is_empty() {
+            return Err(TradeError::NothingAsked);
+        }
+        if self.offer_worth(player, trader) < self.asked_worth(trader) {
+            return Err(TradeError::Refused);
+        }
+        Ok(())
+    }
+}
+
+/// Puts the marked items up to the trader, swapping them if they agree.
+pub fn propose(app: &mut App) -> Result<(), TradeError> {
+    let player = app.world.query_filtered::<Entity, With<PlayerTag>>().iter(&app.world).next();
+    let (player, entity) = match (player, app.world.resource::<LootMenu>().inventory) {
+        (Some(player), Some(entity)) => (player, entity),
+        _ => return Err(TradeError::Unavailable),
+    };
+    let opinion = opinion_of_player(&mut app.world, entity);
+    let loot_menu = app.world.resource::<LootMenu>();
+    let trade = match &loot_menu.trade {
+        Some(trade) => trade,
+        None => return Err(TradeError::Unavailable),
+    };
+    let trader = app.world.get::<Inventory>(entity).map(|inventory| Trader {
+        inventory,
+        equipment: app.world.get::<Equipment>(entity),
+        opinion,
+    });
+    let result = match (app.world.get::<Inventory>(player), trader) {
+        (Some(player_inventory), Some(trader)) => trade.evaluate(player_inventory, &trader),
+        _ => Err(TradeError::Unavailable),
+    };
+    let offered = trade.offered.iter().copied().collect::<Vec<_>>();
+    let asked = trade.asked.iter().copied().collect::<Vec<_>>();
+    let result = result.and_then(|_| {
+        if exchange_items(app, (player, &offered), (entity, &asked)) {
+            Ok(())
+        } else {
+            Err(TradeError::Unavailable)
+        }
+    });
+    if result.is_ok() {
+        app.world.resource_mut::<Relationships>().change(entity, player, TRADE);
+    }
+    if let Some(trade) = &mut app.world.resource_mut::<LootMenu>().trade {
+        if result.is_ok() {
+            trade.offered.clear();
+            trade.asked.clear();
+        }
+        trade.outcome = Some(result);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory(items: Vec<Item>) -> Inventory {
+        Inventory::new(items.into_iter().map(Box::new).collect())
+    }
+
+    fn trader(inventory: &Inventory, opinion: i32) -> Trader<'_> {
+        Trader { inventory, equipment: None, opinion }
+    }
+
+    #[test]
+    fn worth_doubles_when_needed() {
+        let bananas = inventory(vec![Item::new_banana(), Item::new_banana()]);
+        let stocked = trader(&bananas, 0);
+        // They have no keys, but food enough.
+        assert_eq!(stocked.worth(&Item::new_key("Cellar"), false), 2 * Item::new_key("Cellar").value());
+        assert_eq!(stocked.worth(&Item::new_apple(), false), Item::new_apple().value());
+        assert_eq!(stocked.worth(&Item::new_banana(), true), Item::new_banana().value());
+
+        // Their last one.
+        let banana = inventory(vec![Item::new_banana()]);
+        assert_eq!(trader(&banana, 0).worth(&Item::new_banana(), true), 2 * Item::new_banana().value());
+    }
+
+    #[test]
+    fn worth_counts_what_is_held() {
+        let pistol = inventory(vec![Item::new_pistol()]);
+        let equipment = Equipment { equipped: Some(Box::new(Item::new_pistol())) };
+        let trader = Trader { inventory: &pistol, equipment: Some(&equipment), opinion: 0 };
+        assert_eq!(trader.worth(&Item::new_pistol(), true), Item::new_pistol().value());
+    }
+
+    #[test]
+    fn opinion_sways_asking_price() {
+        let bananas = inventory(vec![Item::new_banana(), Item::new_banana()]);
+        let value = Item::new_banana().value();
+        assert_eq!(trader(&bananas, 100).worth(&Item::new_banana(), true), value / 2);
+        assert_eq!(trader(&bananas, -100).worth(&Item::new_banana(), true), value * 3 / 2);
+        // Only what they part with.
+        assert_eq!(trader(&bananas, 100).worth(&Item::new_banana(), false), value);
+
+        // Rounded up in their favour.
+        let apples = inventory(vec![Item::new_apple(), Item::new_apple()]);
+        assert_eq!(Item::new_apple().value(), 3);
+        assert_eq!(trader(&apples, 10).worth(&Item::new_apple(), true), 3);
+        assert_eq!(trader(&apples, 100).worth(&Item::new_apple(), true), 2);
+    }
+
+    #[test]
+    fn evaluate_offers() {
+        let player = inventory(vec![Item::new_pistol(), Item::new_apple()]);
+        let bananas = inventory(vec![Item::new_banana(), Item::new_banana()]);
+        let mut trade = Trade::default();
+        assert_eq!(trade.evaluate(&player, &trader(&bananas, 0)), Err(TradeError::NothingAsked));
+
+        // An apple for a banana.
+        trade.toggle(false, 0);
+        trade.toggle(true, 1);
+        assert_eq!(trade.evaluate(&player, &trader(&bananas, 0)), Err(TradeError::Refused));
+        // Fair enough to a close friend.
+        assert_eq!(trade.offer_worth(&player, &trader(&bananas, 100)), trade.asked_worth(&trader(&bananas, 100)));
+        assert_eq!(trade.evaluate(&player, &trader(&bananas, 100)), Ok(()));
+
+        // A gun they have none of is worth it even to those wary of the player.
+        trade.toggle(true, 1);
+        trade.toggle(true, 0);
+        assert_eq!(trade.evaluate(&player, &trader(&bananas, HOSTILE + 1)), Ok(()));
+        // No offer is good enough for those who cannot stand the player.
+        assert_eq!(trade.evaluate(&player, &trader(&bananas, HOSTILE)), Err(TradeError::Unwelcome));
+    }
+}
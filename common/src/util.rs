@@ -1,5 +1,5 @@
 use bevy::{
-    prelude::Commands,
+    prelude::{Commands, Entity, Name},
     ecs::system::EntityCommands,
 };
 use crate::{
@@ -7,6 +7,7 @@ use crate::{
         Map,
         Position,
         Velocity,
+        Zone,
         Collision,
         CollisionType,
     },
@@ -26,12 +27,17 @@ use crate::{
             werewolf::werewolf_pathfinder,
         },
         werewolf::WerewolfBehavior,
+        rumdare::RumdareBehavior,
     },
     map_brain::CharacterBehaviorData,
-    inventory::Inventory,
+    inventory::{Inventory, Equipment, Item, FloorItem},
+    noise::{Noise, NoiseKind},
+    schedule::{Routine, Patrol},
+    journal::Witness,
+    theft::Owner,
 };
 
-fn spawn_character(
+fn spawn_character<F: FnOnce(EntityCommands)>(
     commands: &mut Commands,
     map: &mut Map,
     sprite: Sprite,
@@ -39,7 +45,7 @@ fn spawn_character(
     health: Health,
     character_type: CharacterType,
     character_data: CharacterData,
-    spawned_callback: fn(EntityCommands),
+    spawned_callback: F,
 ) {
     map.spawn_character(
         commands,
@@ -52,7 +58,8 @@ fn spawn_character(
     );
 }
 
-pub fn spawn_lerain(commands: &mut Commands, map: &mut Map, position: Position) {
+/// Townsfolk carrying the items on top of the pistol everyone has.
+pub fn spawn_lerain(commands: &mut Commands, map: &mut Map, name: &'static str, position: Position, routine: Routine, carrying: Vec<Item>) {
     spawn_character(
         commands,
         map,
@@ -64,12 +71,19 @@ pub fn spawn_lerain(commands: &mut Commands, map: &mut Map, position: Position)
         |mut entity_commands| {
             entity_commands
                 .insert(CharacterBehaviorData::default_human())
-                .insert(PathfinderBehavior::new(1, lerain_pathfinder));
+                .insert(PathfinderBehavior::new(1, lerain_pathfinder))
+                .insert(Name::new(name))
+                .insert(Witness::default())
+                .insert(routine);
+            if !carrying.is_empty() {
+                let items = std::iter::once(Item::new_pistol()).chain(carrying).map(Box::new).collect();
+                entity_commands.insert(Inventory::new(items));
+            }
         },
     )
 }
 
-pub fn spawn_rumdare(commands: &mut Commands, map: &mut Map, position: Position) {
+pub fn spawn_rumdare(commands: &mut Commands, map: &mut Map, name: &'static str, position: Position, patrol: Patrol) {
     spawn_character(
         commands,
         map,
@@ -80,13 +94,18 @@ pub fn spawn_rumdare(commands: &mut Commands, map: &mut Map, position: Position)
         CharacterData::Human,
         |mut entity_commands| {
             entity_commands
-                .insert(CharacterBehaviorData::default_human())
-                .insert(PathfinderBehavior::new(1, rumdare_pathfinder));
+                .insert(CharacterBehaviorData::default_rumdare())
+                .insert(PathfinderBehavior::new(1, rumdare_pathfinder))
+                .insert(RumdareBehavior::new())
+                .insert(Equipment { equipped: Some(Box::new(Item::new_pistol())) })
+                .insert(Name::new(name))
+                .insert(patrol);
         },
     );
 }
 
-pub fn spawn_werewolf(commands: &mut Commands, map: &mut Map, position: Position) {
+/// Spawns the werewolf, looking like any other lerain while human.
+pub fn spawn_werewolf(commands: &mut Commands, map: &mut Map, name: &'static str, position: Position, routine: Routine) {
     spawn_character(
         commands,
         map,
@@ -99,7 +118,9 @@ pub fn spawn_werewolf(commands: &mut Commands, map: &mut Map, position: Position
             entity_commands
                 .insert(CharacterBehaviorData::default_werewolf())
                 .insert(PathfinderBehavior::new(4, werewolf_pathfinder))
-                .insert(WerewolfBehavior::new());
+                .insert(WerewolfBehavior::new())
+                .insert(Name::new(name))
+                .insert(routine);
         },
     );
 }
@@ -107,11 +128,14 @@ pub fn spawn_werewolf(commands: &mut Commands, map: &mut Map, position: Position
 pub fn spawn_projectile(
     commands: &mut Commands,
     map: &mut Map,
+    noise: &mut Noise,
     sprite: Sprite,
     position: Position,
     velocity: Velocity,
     damage: i32,
+    shooter: Option<Entity>,
 ) {
+    noise.emit(map, NoiseKind::Gunshot, position, shooter);
     map.spawn(
         commands,
         sprite,
@@ -124,6 +148,7 @@ pub fn spawn_projectile(
                 .insert(Interact::new(crate::character::InteractData::Projectile {
                     recent_spawn: true,
                     damage,
+                    shooter,
                 }))
                 .insert(Collision::new(CollisionType::Sensor));
         },
@@ -136,6 +161,11 @@ pub fn spawn_chest(
     position: Position,
     inventory: Inventory,
 ) {
+    // Chests in someone's home are theirs.
+    let owner = match map.zone_at(&position) {
+        Some(Zone::Home { owner: Some(owner) }) => Some(Owner(owner.clone())),
+        _ => None,
+    };
     map.spawn(
         commands,
         Sprite::Chest,
@@ -146,10 +176,27 @@ pub fn spawn_chest(
             entity_commands
                 .insert(LootableTag)
                 .insert(inventory);
+            if let Some(owner) = owner {
+                entity_commands.insert(owner);
+            }
         },
     );
 }
 
+/// Puts the item on the ground, it shares the tile with whatever else is there.
+pub fn spawn_floor_item(
+    commands: &mut Commands,
+    map: &Map,
+    position: Position,
+    item: Box<Item>,
+) {
+    commands.spawn()
+        .insert(Sprite::Item)
+        .insert(position)
+        .insert(map.level())
+        .insert(FloorItem(item));
+}
+
 #[derive(Debug)]
 pub struct Cooldown(pub usize);
 impl Cooldown {
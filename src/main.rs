@@ -3,19 +3,34 @@ use client::render::*;
 use common::{
     physics::*,
     character::*,
-    dialogue::Dialogue,
+    dialogue::{Dialogue, DialogueTrees},
     ActionInput,
     Scene,
-    behaviors::pathfinder::data::PathfinderGlobalData,
+    behaviors::pathfinder::{PathfinderBudget, data::PathfinderGlobalData, rooms::RoomGraph, reservations::{Reservations, swap_update}, danger::DangerZones},
     PlayerState,
+    Settings,
     loot_menu::LootMenu,
-    map_setup::town,
-    inventory::inventory_update,
+    map_setup::{town, cellar},
+    inventory::{inventory_update, pick_up_update},
+    door::door_update,
+    level::{Levels, level_update},
+    chunks::active_chunks_update,
+    spatial::{SpatialIndex, spatial_index_update, spatial_index_removal_update},
+    interaction::Interactions,
+    relationship::{Relationships, relationship_update},
+    theft::Thefts,
+    noise::{Noise, noise_update},
+    time::{Clock, clock_update},
+    schedule::Schedules,
+    journal::{Journal, journal_update, witness_update},
+    quest::{Quests, quest_update},
+    behaviors::rumdare::{RumdareAlert, rumdare_update},
 };
 use iyes_loopless::condition::IntoConditionalExclusiveSystem;
 
-fn setup(mut commands: Commands, mut map: ResMut<Map>, pathfinder_data: Res<PathfinderGlobalData>) {
-    town(&mut commands, &mut map, &pathfinder_data);
+fn setup(mut commands: Commands, mut map: ResMut<Map>, mut levels: ResMut<Levels>, pathfinder_data: Res<PathfinderGlobalData>, schedules: Res<Schedules>) {
+    town(&mut commands, &mut map, &pathfinder_data, &schedules);
+    cellar(&mut commands, &mut map, &mut levels);
 }
 
 fn pause_main_game(player_state: Res<PlayerState>) -> bool {
@@ -28,13 +43,25 @@ fn pause_main_game(player_state: Res<PlayerState>) -> bool {
 
 fn main() {
 
+    const CLOCK_UPDATE_LABEL: &str = "clock_update";
+    const NOISE_UPDATE_LABEL: &str = "noise_update";
+    const DOOR_UPDATE_LABEL: &str = "door_update";
     const PLAYER_INPUT_LABEL: &str = "player_movement_input_update";
     const PLAYER_MOVEMENT_LABEL: &str = "player_movement_update";
+    const SPATIAL_INDEX_LABEL: &str = "spatial_index_update";
+    const ACTIVE_CHUNKS_LABEL: &str = "active_chunks_update";
+    const RELATIONSHIP_UPDATE_LABEL: &str = "relationship_update";
     const NPC_BEHAVIOR_UPDATE_LABEL: &str = "npc_behavior_update";
+    const NPC_SWAP_UPDATE_LABEL: &str = "npc_swap_update";
     const NPC_MOVEMENT_UPDATE_LABEL: &str = "npc_movement_update";
     const COLLISION_UPDATE_LABEL: &str = "collision_update";
     const INTERACT_UPDATE_LABEL: &str = "interact_update";
     const DESTORY_CHECK_LABEL: &str = "destroy_check";
+    const PICK_UP_LABEL: &str = "pick_up_update";
+    const WITNESS_UPDATE_LABEL: &str = "witness_update";
+    const JOURNAL_UPDATE_LABEL: &str = "journal_update";
+    const QUEST_UPDATE_LABEL: &str = "quest_update";
+    const LEVEL_UPDATE_LABEL: &str = "level_update";
 
     const INVENTORY_LABEL: &str = "inventory_update";
 
@@ -42,20 +69,55 @@ fn main() {
         .set_runner(runner)
         .add_state(Scene::Map)
         .insert_resource(PlayerState::default())
+        .insert_resource(Settings::default())
         .init_resource::<PlayerInput>()
         .insert_resource(ActionInput::None)
         .insert_resource(Dialogue::default())
+        .init_resource::<DialogueTrees>()
         .insert_resource(LootMenu::default())
         .init_resource::<PathfinderGlobalData>()
+        .insert_resource(PathfinderBudget::default())
+        .insert_resource(RoomGraph::default())
+        .insert_resource(Reservations::default())
+        .insert_resource(DangerZones::default())
         .insert_resource(MapCache::default())
+        .insert_resource(Noise::default())
+        .insert_resource(Clock::default())
+        .init_resource::<Schedules>()
+        .insert_resource(Journal::default())
+        .init_resource::<Quests>()
+        .insert_resource(RumdareAlert::default())
         .init_resource::<Map>()
+        .init_resource::<Levels>()
+        .init_resource::<SpatialIndex>()
+        .init_resource::<Interactions>()
+        .init_resource::<Relationships>()
+        .init_resource::<Thefts>()
         .add_startup_system(setup)
 
         .add_system_set(SystemSet::on_update(Scene::Map)
+            .with_system(
+                clock_update
+                    .run_if_not(pause_main_game)
+                    .label(CLOCK_UPDATE_LABEL)
+            )
+            .with_system(
+                noise_update
+                    .run_if_not(pause_main_game)
+                    .label(NOISE_UPDATE_LABEL)
+                    .after(CLOCK_UPDATE_LABEL)
+            )
+            .with_system(
+                door_update
+                    .run_if_not(pause_main_game)
+                    .label(DOOR_UPDATE_LABEL)
+                    .after(NOISE_UPDATE_LABEL)
+            )
             .with_system(
                 inventory_update
                     .run_if_not(pause_main_game)
                     .label(INVENTORY_LABEL)
+                    .after(DOOR_UPDATE_LABEL)
             )
             .with_system(
                 player_movement_input_update
@@ -69,18 +131,43 @@ fn main() {
                     .label(PLAYER_MOVEMENT_LABEL)
                     .after(PLAYER_INPUT_LABEL)
             )
+            .with_system(
+                spatial_index_update
+                    .run_if_not(pause_main_game)
+                    .label(SPATIAL_INDEX_LABEL)
+                    .after(PLAYER_MOVEMENT_LABEL)
+            )
+            .with_system(
+                active_chunks_update
+                    .run_if_not(pause_main_game)
+                    .label(ACTIVE_CHUNKS_LABEL)
+                    .after(SPATIAL_INDEX_LABEL)
+            )
+            .with_system(
+                relationship_update
+                    .run_if_not(pause_main_game)
+                    .label(RELATIONSHIP_UPDATE_LABEL)
+                    .after(ACTIVE_CHUNKS_LABEL)
+            )
             .with_system(
                 common::behaviors::pathfinder::pathfinder_update
                 .chain(common::behaviors::werewolf::werewolf_update)
+                .chain(rumdare_update)
                 .run_if_not(pause_main_game)
                 .label(NPC_BEHAVIOR_UPDATE_LABEL)
-                .after(PLAYER_MOVEMENT_LABEL)
+                .after(RELATIONSHIP_UPDATE_LABEL)
+            )
+            .with_system(
+                swap_update
+                    .run_if_not(pause_main_game)
+                    .label(NPC_SWAP_UPDATE_LABEL)
+                    .after(NPC_BEHAVIOR_UPDATE_LABEL)
             )
             .with_system(
                 npc_movement_update
                     .run_if_not(pause_main_game)
                     .label(NPC_MOVEMENT_UPDATE_LABEL)
-                    .after(NPC_BEHAVIOR_UPDATE_LABEL)
+                    .after(NPC_SWAP_UPDATE_LABEL)
             )
             .with_system(
                 collision_update
@@ -100,8 +187,40 @@ fn main() {
                     .label(DESTORY_CHECK_LABEL)
                     .after(INTERACT_UPDATE_LABEL)
             )
+            .with_system(
+                pick_up_update
+                    .run_if_not(pause_main_game)
+                    .label(PICK_UP_LABEL)
+                    .after(DESTORY_CHECK_LABEL)
+            )
+            .with_system(
+                witness_update
+                    .run_if_not(pause_main_game)
+                    .label(WITNESS_UPDATE_LABEL)
+                    .after(PICK_UP_LABEL)
+            )
+            .with_system(
+                journal_update
+                    .run_if_not(pause_main_game)
+                    .label(JOURNAL_UPDATE_LABEL)
+                    .after(WITNESS_UPDATE_LABEL)
+            )
+            .with_system(
+                quest_update
+                    .run_if_not(pause_main_game)
+                    .label(QUEST_UPDATE_LABEL)
+                    .after(JOURNAL_UPDATE_LABEL)
+            )
+            .with_system(
+                level_update
+                    .run_if_not(pause_main_game)
+                    .label(LEVEL_UPDATE_LABEL)
+                    .after(QUEST_UPDATE_LABEL)
+            )
         )
 
+        .add_system_to_stage(CoreStage::PostUpdate, spatial_index_removal_update)
+
         .add_system_set(SystemSet::on_update(Scene::Inventory)
             .with_system(
                 inventory_update